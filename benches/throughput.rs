@@ -0,0 +1,93 @@
+// Candidates per second for typical recipes, measured on the release binary
+// Run with `cargo bench`; add a name to only run the benchmarks containing it, e.g. `cargo bench -- replace`
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+struct Benchmark {
+    name: &'static str,
+    recipe: &'static str
+}
+
+const BENCHMARKS: &[Benchmark] = &[
+    Benchmark { name: "mask", recipe: "mask ?l?l?l?l?d" },
+    Benchmark { name: "wordlist_mask", recipe: "wordlist words.txt\nmask ?d?d?d" },
+    Benchmark { name: "case_modifiers", recipe: "wordlist+ulto words.txt\nmask ?d?d" },
+    Benchmark { name: "optional", recipe: "wordlist words.txt\nmask? ?d?d\nconstant? !" },
+    Benchmark { name: "concat", recipe: "wordlist#w words.txt\nmask#d ?d?d\nconcat+ut #w #d" },
+    Benchmark { name: "rearrange", recipe: "wordlist#w words.txt\nmask#d ?d?d\nconstant#s !\nrearrange #w #d #s" },
+    Benchmark { name: "replace", recipe: "wordlist words.txt\nreplace #1 a4 e3 i1 o0 s5\nmask ?d?d" },
+    Benchmark { name: "replace_all", recipe: "wordlist words.txt\nreplace+all #1 a=4@ e3 i=1! o0 s=5$\nmask ?d" },
+    Benchmark { name: "rules", recipe: "wordlist words.txt\nrules #1 bench.rule\nmask ?d" },
+    Benchmark { name: "upstream_case_inner_mask", recipe: "wordlist+t words.txt\nmask ?d?d?d" },
+    // The concat step (toggled with +t) comes after the mask but only reads the word, so it doesn't need recomputing for every digit
+    Benchmark { name: "word_step_after_mask", recipe: "wordlist#w words.txt\nmask ?d?d?d\nconcat+t #w #w" }
+];
+
+const NUM_WORDS: usize = 10000;
+const RULES: &str = ":\nu\nc\nr\nd\n$1\n^!\nsa@\nT0\n$2 $0 $2 $4\n";
+
+// Deterministic pseudo-random lowercase words, so runs are comparable
+fn generate_words() -> String {
+    let mut state: u64 = 0x2545F4914F6CDD1D;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let mut words = String::new();
+    for _ in 0..NUM_WORDS {
+        let len = 5 + next() % 6;
+        for _ in 0..len {
+            words.push((b'a' + (next() % 26) as u8) as char);
+        }
+        words.push('\n');
+    }
+    words
+}
+
+// Runs the recipe and returns the number of candidates and the seconds taken
+fn run(dir: &Path, benchmark: &Benchmark) -> io::Result<(u64, f64)> {
+    let recipe_path = dir.join(format!("{}.txt", benchmark.name));
+    fs::write(&recipe_path, benchmark.recipe)?;
+
+    let start = Instant::now();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_PasswordChef"))
+        .arg("--recipe").arg(&recipe_path)
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdout = child.stdout.take().unwrap();
+    let mut buffer = vec![0u8; 1 << 16];
+    let mut candidates = 0;
+    loop {
+        let n = stdout.read(&mut buffer)?;
+        if n == 0 { break; }
+        candidates += buffer[..n].iter().filter(|b| **b == b'\n').count() as u64;
+    }
+    if !child.wait()?.success() {
+        return Err(io::Error::other(format!("{} failed", benchmark.name)));
+    }
+    Ok((candidates, start.elapsed().as_secs_f64()))
+}
+
+fn main() -> io::Result<()> {
+    // Cargo passes options like --bench; anything else is a filter
+    let filters: Vec<String> = std::env::args().skip(1).filter(|arg| !arg.starts_with("--")).collect();
+
+    let dir = std::env::temp_dir().join(format!("passwordchef-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("words.txt"), generate_words())?;
+    fs::write(dir.join("bench.rule"), RULES)?;
+
+    println!("{:<26} {:>12} {:>10} {:>14}", "benchmark", "candidates", "seconds", "candidates/s");
+    for benchmark in BENCHMARKS {
+        if !filters.is_empty() && !filters.iter().any(|f| benchmark.name.contains(f.as_str())) { continue; }
+        let (candidates, seconds) = run(&dir, benchmark)?;
+        println!("{:<26} {:>12} {:>10.3} {:>14.0}", benchmark.name, candidates, seconds, candidates as f64 / seconds);
+    }
+
+    fs::remove_dir_all(&dir)
+}
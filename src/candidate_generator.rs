@@ -2,147 +2,344 @@ use std::cell::RefCell;
 use std::cmp::PartialEq;
 use std::collections::HashMap;
 use std::fmt::Display;
-use std::io::{BufWriter, Write};
+use std::io::Write;
 use std::mem;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use flexstr::{local_str, LocalStr};
 use itertools::Itertools;
-use smallvec::{smallvec, SmallVec};
 use RecipeStep::*;
 use crate::items::case_modifier::CaseModifierIncrementer;
 use crate::items::concat::ConcatIncrementer;
 use crate::recipe_step::*;
 use crate::items::incrementer_trait::RecipeIncrementer;
-use crate::items::wordlist::WordlistIncrementer;
+use crate::items::wordlist::{WordFilter, WordlistIncrementer, WordlistMode};
 use crate::items::constant::ConstantIncrementer;
 use crate::items::duplicate::DuplicateIncrementer;
-use crate::items::mask::MaskIncrementer;
+use crate::items::mask::{CustomCharsets, MaskCharType, MaskIncrementalIncrementer, MaskIncrementer};
 use crate::items::optional_modifier::OptionalModifierIncrementer;
 use crate::items::rearrange::RearrangeIncrementer;
-use crate::items::replace::ReplaceIncrementer;
-
-pub const SV_SIZE: usize = 4;
+use crate::items::replace::{ReplaceAllIncrementer, ReplaceIncrementer};
+use crate::items::rules::RulesIncrementer;
+use crate::keyspace::Keyspace;
+use crate::recipe_parser::RecipeParser;
+use crate::checkpoint::{Checkpoint, Fingerprint};
+use crate::custom_step::{CustomStepContext, StepRegistry};
 
 pub type IncIndex = usize;
 pub type SegIndex = usize;
 
 pub struct CandidateGenerator {
     incrementers: Vec<Box<dyn RecipeIncrementer>>,
-    write_indices: Vec<SmallVec<[SegIndex; SV_SIZE]>>,     // Which text segments an incrementer will write to
-    text_segments: Vec<LocalStr>,
+    write_indices: Vec<Range<SegIndex>>,                   // Which text segments an incrementer will write to
+    dependents: Vec<Vec<IncIndex>>,                        // Later incrementers that read an incrementer's text, directly or through others
+    text_segments: Vec<String>,                            // Reused between candidates, so their allocations are kept
     output_indices: Vec<SegIndex>,                         // Which text segments will be outputted as the password and in what order
-    buffer: String,
-    writer: Box<BufWriter<dyn Write>>,
+    dirty: Vec<bool>,                                      // Incrementers whose segments have to be recomputed
+    first_dirty: IncIndex,                                 // No incrementer before this one is dirty
+    advanced: Vec<bool>,                                   // Incrementers that moved past their first entry since their last reset
+    buffer: String,                                        // The candidate last returned
+    finished: bool,                                        // Every candidate has been returned, or the current unit of work is done
+    error: Option<std::io::Error>,                         // Stops generating early, e.g. a wordlist that couldn't be read
+    limit: Option<u128>,                                   // Number of candidates left to print, if limited
+    custom_charsets: CustomCharsets,                       // Charsets for ?1 to ?4 after all charset steps
+    warnings: Vec<String>,                                 // Problems found while loading the recipe's files that didn't stop it, e.g. skipped rules
+    fingerprint: u64,                                      // Of the recipe and its wordlists, saved in checkpoints
+    unit_prefixes_left: u128                               // Combinations of the first incrementers left in the current unit of work
+}
+
+// How candidates are divided between threads: a unit of work is a block of consecutive combinations of values
+// of the first incrementers, covering every candidate the later incrementers generate with them
+#[derive(Debug, Clone)]
+pub struct WorkSplit {
+    radices: Vec<u128>,     // Keyspaces of the first incrementers
+    block_size: u128
+}
+
+impl WorkSplit {
+    pub fn units(&self) -> u128 {
+        self.radices.iter().product::<u128>().div_ceil(self.block_size)
+    }
+}
+
+// Settings that come from outside the recipe, e.g. from command line arguments
+#[derive(Debug, Default, Clone)]
+pub struct GeneratorOptions {
+    pub custom_charsets: CustomCharsets,                   // Already expanded; charset steps in the recipe replace these
+    pub search_dirs: Vec<PathBuf>,                         // Where relative file paths in the recipe are looked for, in order
+    pub wordlist_mode: WordlistMode,
+    pub custom_steps: StepRegistry                         // Step types added by library users
+}
+
+impl GeneratorOptions {
+    // Relative paths are tried in each search directory and then the current directory (the empty path)
+    fn dirs_to_search(&self, path: &Path) -> Vec<PathBuf> {
+        if path.is_absolute() { return vec![PathBuf::new()]; }
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        for dir in self.search_dirs.iter().cloned().chain([PathBuf::new()]) {
+            if !dirs.contains(&dir) { dirs.push(dir); }
+        }
+        dirs
+    }
+
+    fn not_found(what: &str, searched: &[PathBuf]) -> String {
+        format!("{}; searched:{}", what, searched.iter().map(|p| format!("\n    {}", p.display())).collect::<String>())
+    }
+
+    // Finds a file used by the recipe
+    pub fn resolve_file(&self, filename: &str) -> Result<String, String> {
+        let path = Path::new(filename);
+        let candidates: Vec<PathBuf> = self.dirs_to_search(path).iter().map(|dir| dir.join(path)).collect();
+        match candidates.iter().find(|p| p.is_file()) {
+            Some(found) => Ok(found.to_string_lossy().into_owned()),
+            None => Err(Self::not_found(&format!("file `{}` not found", filename), &candidates))
+        }
+    }
+
+    // Finds the files of a wordlist step, which can be a single file, a directory (all files in it)
+    // or a glob pattern like `names/*.txt`; files are sorted by name
+    pub fn resolve_wordlist(&self, pattern: &str) -> Result<Vec<String>, String> {
+        let path = Path::new(pattern);
+        let dirs = self.dirs_to_search(path);
+        if pattern.contains(['*', '?', '[']) {
+            let mut searched = Vec::new();
+            for dir in dirs {
+                // Only the recipe's pattern can contain wildcards
+                let full_pattern = Path::new(&glob::Pattern::escape(&dir.to_string_lossy())).join(path);
+                let files: Vec<String> = glob::glob(&full_pattern.to_string_lossy())
+                    .map_err(|e| format!("invalid pattern `{}`: {}", pattern, e))?
+                    .filter_map(Result::ok)
+                    .filter(|p| p.is_file())
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .collect();
+                if !files.is_empty() { return Ok(files); }
+                searched.push(full_pattern);
+            }
+            return Err(Self::not_found(&format!("no files match `{}`", pattern), &searched));
+        }
+
+        let candidates: Vec<PathBuf> = dirs.iter().map(|dir| dir.join(path)).collect();
+        let Some(found) = candidates.iter().find(|p| p.exists()) else {
+            return Err(Self::not_found(&format!("file or directory `{}` not found", pattern), &candidates));
+        };
+        if !found.is_dir() { return Ok(vec![found.to_string_lossy().into_owned()]); }
+
+        let entries = std::fs::read_dir(found).map_err(|e| format!("can't read directory {}: {}", found.display(), e))?;
+        let files: Vec<String> = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            // Skip hidden files like .DS_Store
+            .filter(|p| p.is_file() && !p.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')))
+            .map(|p| p.to_string_lossy().into_owned())
+            .sorted()
+            .collect();
+        if files.is_empty() { return Err(format!("directory `{}` has no files", found.display())); }
+        Ok(files)
+    }
 }
 
 struct CandidateGeneratorFields {
     incrementers: Vec<Box<dyn RecipeIncrementer>>,
-    write_indices: Vec<SmallVec<[SegIndex; SV_SIZE]>>,
+    write_indices: Vec<Range<SegIndex>>,
+    read_indices: Vec<Vec<SegIndex>>,       // Text segments each incrementer reads
     output_indices: Vec<SegIndex>,
     // #ID or .class -> text segment index
     id_map: HashMap<String, SegIndex>,
     class_map: HashMap<String, Vec<SegIndex>>,
-    cur_seg_idx: SegIndex
+    cur_seg_idx: SegIndex,
+    custom_charsets: CustomCharsets,
+    warnings: Vec<String>
 }
 
 impl CandidateGenerator {
-    pub fn from_recipe(recipe: Recipe, writer: Box<BufWriter<dyn Write>>) -> CandidateGenerator {
+    // Fails when a file used by the recipe can't be read, or the recipe refers to IDs it doesn't have;
+    // RecipeValidator reports the latter with more detail
+    pub fn from_recipe(recipe: Recipe, options: GeneratorOptions) -> Result<CandidateGenerator, String> {
 
         let mut fields = CandidateGeneratorFields {
             incrementers: Vec::new(),
             write_indices: Vec::new(),
+            read_indices: Vec::new(),
             output_indices: Vec::new(),
             cur_seg_idx: 0,
             id_map: HashMap::new(),
-            class_map: HashMap::new()
+            class_map: HashMap::new(),
+            custom_charsets: options.custom_charsets.clone(),
+            warnings: Vec::new()
         };
+        // The parsed recipe, so that comments and spacing don't matter
+        let mut fingerprint = Fingerprint::default();
+        fingerprint.add(format!("{:?}", recipe).as_bytes());
 
         for (i, step) in recipe.into_iter().enumerate() {
             let step_id_idx = i + 1;
-            let id_to_seg_idx = |id| *fields.id_map.get(id).expect("ERROR: ID doesn't exist");
+            let id_to_seg_idx = |id: &String| fields.id_map.get(id).copied().ok_or_else(|| format!("ID `{}` doesn't exist", id));
             match step {
-                Wordlist { filename, attr, modifiers } => {
-                    Self::add_inc(WordlistIncrementer::new(filename), attr, modifiers, step_id_idx, &mut fields);
+                Wordlist { filename, options: wordlist_options, attr, modifiers } => {
+                    let filter = WordFilter::new(wordlist_options, &fields.custom_charsets)?;
+                    let filenames = options.resolve_wordlist(&filename)?;
+                    for filename in &filenames {
+                        fingerprint.add_file(filename)?;
+                    }
+                    Self::add_inc(WordlistIncrementer::new(filenames, options.wordlist_mode, filter)?, &[], attr, modifiers, step_id_idx, &mut fields)?;
                 }
                 Constant { value, attr, modifiers } => {
-                    Self::add_inc(ConstantIncrementer::new(value), attr, modifiers, step_id_idx, &mut fields);
+                    Self::add_inc(ConstantIncrementer::new(value), &[], attr, modifiers, step_id_idx, &mut fields)?;
                 }
                 Duplicate { target_id, attr, modifiers } => {
-                    Self::add_inc(DuplicateIncrementer::new(id_to_seg_idx(&target_id)),
-                                  attr, modifiers, step_id_idx, &mut fields);
+                    let target_seg = id_to_seg_idx(&target_id)?;
+                    Self::add_inc(DuplicateIncrementer::new(target_seg), &[target_seg],
+                                  attr, modifiers, step_id_idx, &mut fields)?;
                 }
                 Mask { mask, attr, modifiers } => {
-                    Self::add_inc(MaskIncrementer::new(mask), attr, modifiers, step_id_idx, &mut fields);
+                    Self::add_inc(MaskIncrementer::new(mask, &fields.custom_charsets)?, &[], attr, modifiers, step_id_idx, &mut fields)?;
+                }
+                MaskIncremental { mask, min_len, max_len, attr, modifiers } => {
+                    Self::add_inc(MaskIncrementalIncrementer::new(mask, min_len, max_len, &fields.custom_charsets)?, &[],
+                                  attr, modifiers, step_id_idx, &mut fields)?;
                 }
                 Rearrange { target_list } => {
-                    let source_seg_indices: Vec<SegIndex> = target_list.iter()
-                        .flat_map(|tag| Self::tag_to_seg_indices(tag, &fields))
-                        .collect();
+                    let source_seg_indices = Self::tags_to_seg_indices(&target_list, &fields)?;
                     Self::add_multimod_incrementer(
                         RearrangeIncrementer::new(source_seg_indices.clone()),
                         &source_seg_indices,
                         &mut fields
                     )
                 }
-                ReplaceChar { target_id, replacements, attr, modifiers } => {
-                    let target_seg = id_to_seg_idx(&target_id);
+                Replace { target_id, mut replacements, table_files, mode, attr, modifiers } => {
+                    let target_seg = id_to_seg_idx(&target_id)?;
+                    // Entries from substitution table files come after the ones written in the step
+                    for filename in table_files {
+                        let filename = options.resolve_file(&filename)?;
+                        let table_text = std::fs::read_to_string(&filename)
+                            .map_err(|e| format!("can't read {}: {}", filename, e))?;
+                        replacements.extend(RecipeParser::parse_replacement_table(&table_text)
+                            .map_err(|e| format!("invalid substitution table {}: {}", filename, e))?);
+                    }
+                    Self::remove_seg_from_output(target_seg, &mut fields);
+                    match mode {
+                        ReplaceMode::Single =>
+                            Self::add_inc(ReplaceIncrementer::new(target_seg, replacements), &[target_seg], attr, modifiers, step_id_idx, &mut fields)?,
+                        ReplaceMode::All { max_substitutions } =>
+                            Self::add_inc(ReplaceAllIncrementer::new(target_seg, replacements, max_substitutions), &[target_seg],
+                                          attr, modifiers, step_id_idx, &mut fields)?
+                    }
+                }
+                Rules { target_id, filename, attr, modifiers } => {
+                    let target_seg = id_to_seg_idx(&target_id)?;
+                    let (inc, warnings) = RulesIncrementer::new(target_seg, options.resolve_file(&filename)?)?;
+                    fields.warnings.extend(warnings);
                     Self::remove_seg_from_output(target_seg, &mut fields);
-                    Self::add_inc(ReplaceIncrementer::new(target_seg, replacements), attr, modifiers, step_id_idx, &mut fields);
+                    Self::add_inc(inc, &[target_seg], attr, modifiers, step_id_idx, &mut fields)?;
+                }
+                Custom { step, target_id, args, attr, modifiers } => {
+                    let target_seg = target_id.as_ref().map(id_to_seg_idx).transpose()?;
+                    let context = CustomStepContext { target_seg, custom_charsets: &fields.custom_charsets };
+                    let inc = step.build(&args, &context);
+                    if let Some(target_seg) = target_seg {
+                        Self::remove_seg_from_output(target_seg, &mut fields);
+                    }
+                    Self::add_boxed_inc(inc, target_seg.as_slice(), attr, modifiers, step_id_idx, &mut fields)?;
                 }
                 Concat { target_list, attr, modifiers } => {
-                    let source_seg_indices: Vec<SegIndex> = target_list.iter()
-                        .flat_map(|tag| Self::tag_to_seg_indices(tag, &fields))
-                        .collect();
+                    let source_seg_indices = Self::tags_to_seg_indices(&target_list, &fields)?;
                     for source_seg in &source_seg_indices {
                         Self::remove_seg_from_output(*source_seg, &mut fields);
                     }
-                    Self::add_inc(ConcatIncrementer::new(source_seg_indices), attr, modifiers, step_id_idx, &mut fields)
+                    Self::add_inc(ConcatIncrementer::new(source_seg_indices.clone()), &source_seg_indices, attr, modifiers, step_id_idx, &mut fields)?;
+                }
+                Charset { slot, definition } => {
+                    fields.custom_charsets[slot] = MaskCharType::expand_charset(&definition, &fields.custom_charsets)
+                        .map_err(|e| format!("invalid charset: {}", e))?;
+                }
+                Location { attr } => {
+                    // Anchor is an empty segment that marks a position in the output
+                    Self::add_inc(ConstantIncrementer::new(String::new()), &[], attr, Default::default(), step_id_idx, &mut fields)?;
+                }
+                Insert { location_id, target_list } => {
+                    let location_seg = id_to_seg_idx(&location_id)?;
+                    let source_seg_indices = Self::tags_to_seg_indices(&target_list, &fields)?;
+                    Self::insert_segs_at_location(&source_seg_indices, location_seg, &mut fields)?;
+                }
+            }
+        }
+
+        // Dependency graph: when an incrementer changes, its dependents have to be recomputed too
+        let num_incs = fields.incrementers.len();
+        let mut dependents: Vec<Vec<IncIndex>> = vec![Vec::new(); num_incs];
+        for (inc_idx, read_segs) in fields.read_indices.iter().enumerate() {
+            let sources: Vec<IncIndex> = fields.write_indices.iter()
+                .positions(|write_range| read_segs.iter().any(|seg| write_range.contains(seg)))
+                .collect();
+            // Sources come before, so their own dependents are already known
+            for (src, src_dependents) in dependents.iter_mut().enumerate().take(inc_idx) {
+                if sources.contains(&src) || sources.iter().any(|s| src_dependents.contains(s)) {
+                    src_dependents.push(inc_idx);
                 }
-                _ => {}
             }
         }
 
-        CandidateGenerator {
+        Ok(CandidateGenerator {
             incrementers: fields.incrementers,
             write_indices: fields.write_indices,
-            text_segments: vec![local_str!(""); fields.cur_seg_idx],
+            dependents,
+            text_segments: vec![String::new(); fields.cur_seg_idx],
             output_indices: fields.output_indices,
+            dirty: vec![true; num_incs],
+            first_dirty: 0,
+            advanced: vec![true; num_incs],
             buffer: String::new(),
-            writer,
-        }
+            finished: false,
+            error: None,
+            limit: None,
+            custom_charsets: fields.custom_charsets,
+            warnings: fields.warnings,
+            fingerprint: fingerprint.value(),
+            unit_prefixes_left: 0
+        })
     }
 
     // Shorthand for adding an incrementer, adding modifiers, and updating IDs and classes maps
     fn add_inc<T: RecipeIncrementer + 'static>(
-        inc: T, attr: CommonAttributes, modifiers: GeneratorModifiers,
+        inc: T, source_seg_indices: &[SegIndex], attr: CommonAttributes, modifiers: GeneratorModifiers,
         step_id_idx: usize, fields: &mut CandidateGeneratorFields
-    ) {
-        Self::add_basic_incrementer(inc, fields);
+    ) -> Result<(), String> {
+        Self::add_boxed_inc(Box::new(inc), source_seg_indices, attr, modifiers, step_id_idx, fields)
+    }
+
+    fn add_boxed_inc(
+        inc: Box<dyn RecipeIncrementer>, source_seg_indices: &[SegIndex], attr: CommonAttributes, modifiers: GeneratorModifiers,
+        step_id_idx: usize, fields: &mut CandidateGeneratorFields
+    ) -> Result<(), String> {
+        Self::add_basic_incrementer(inc, source_seg_indices, fields);
         Self::add_modifiers(fields.cur_seg_idx - 1, modifiers, fields);
-        Self::add_attr(fields.cur_seg_idx - 1, step_id_idx, attr, &mut fields.id_map, &mut fields.class_map);
+        Self::add_attr(fields.cur_seg_idx - 1, step_id_idx, attr, &mut fields.id_map, &mut fields.class_map)
     }
 
     fn add_attr(seg_idx: SegIndex, step_id_idx: usize, attr: CommonAttributes,
-                id_map: &mut HashMap<String, SegIndex>, class_map: &mut HashMap<String, Vec<SegIndex>>) {
+                id_map: &mut HashMap<String, SegIndex>, class_map: &mut HashMap<String, Vec<SegIndex>>) -> Result<(), String> {
         id_map.insert(format!("#{}", step_id_idx), seg_idx);
         // Assumes that the recipe parser kept # and . in the ID/class names
         if let Some(id) = attr.id {
-            let prev_seg = id_map.insert(id, seg_idx);
-            if prev_seg.is_some() { panic!("ERROR: duplicate ID in recipe"); }
+            if id_map.contains_key(&id) { return Err(format!("duplicate ID `{}` in recipe", id)); }
+            id_map.insert(id, seg_idx);
         }
         for class in attr.classes {
             class_map.entry(class)
                 .and_modify(|v| v.push(seg_idx))
                 .or_insert(vec![seg_idx]);
         }
+        Ok(())
     }
 
-    fn add_basic_incrementer<T: RecipeIncrementer + 'static>(
-        inc: T,
+    fn add_basic_incrementer(
+        inc: Box<dyn RecipeIncrementer>,
+        source_seg_indices: &[SegIndex],
         mut fields: &mut CandidateGeneratorFields
     ) {
-        fields.incrementers.push(Box::new(inc));
-        fields.write_indices.push(smallvec![fields.cur_seg_idx]);
+        fields.incrementers.push(inc);
+        fields.write_indices.push(fields.cur_seg_idx..fields.cur_seg_idx + 1);
+        fields.read_indices.push(source_seg_indices.to_vec());
         fields.output_indices.push(fields.cur_seg_idx);
         fields.cur_seg_idx += 1;
     }
@@ -150,11 +347,13 @@ impl CandidateGenerator {
     fn add_multi_incrementer<T: RecipeIncrementer + 'static>(
         inc: T,
         num_outputs: usize,
+        source_seg_indices: &[SegIndex],
         mut fields: &mut CandidateGeneratorFields
     ) {
         fields.incrementers.push(Box::new(inc));
-        let range = (fields.cur_seg_idx .. (fields.cur_seg_idx + num_outputs)).collect_vec();
-        fields.write_indices.push(range.iter().copied().collect::<SmallVec<[SegIndex; SV_SIZE]>>());
+        let range = fields.cur_seg_idx .. (fields.cur_seg_idx + num_outputs);
+        fields.write_indices.push(range.clone());
+        fields.read_indices.push(source_seg_indices.to_vec());
         for seg_idx in range {
             fields.output_indices.push(fields.cur_seg_idx);
             fields.cur_seg_idx += 1;
@@ -163,13 +362,14 @@ impl CandidateGenerator {
 
     fn add_multimod_incrementer<T: RecipeIncrementer + 'static>(
         inc: T,
-        source_seg_indices: &Vec<SegIndex>,
+        source_seg_indices: &[SegIndex],
         mut fields: &mut CandidateGeneratorFields
     ) {
         fields.incrementers.push(Box::new(inc));
         let n = source_seg_indices.len();
         let out_seg_range: Vec<SegIndex> = (fields.cur_seg_idx .. (fields.cur_seg_idx + n)).collect_vec();
-        fields.write_indices.push(out_seg_range.iter().copied().collect::<SmallVec<[SegIndex; SV_SIZE]>>());
+        fields.write_indices.push(out_seg_range[0]..out_seg_range[0] + n);
+        fields.read_indices.push(source_seg_indices.to_vec());
         // println!("SOURCE SEGS: {:?}", source_seg_indices);
         // println!("OUT SEG RANGE: {:?}", out_seg_range);
         // println!("OUT INDICES: {:?}", fields.output_indices);
@@ -192,24 +392,24 @@ impl CandidateGenerator {
     ) {
         // Case modifiers
         if modifiers.case != CaseModifiers::default() {
-            Self::add_basic_incrementer(CaseModifierIncrementer::new(source_seg_idx, modifiers.case), fields);
+            Self::add_basic_incrementer(Box::new(CaseModifierIncrementer::new(source_seg_idx, modifiers.case)), &[source_seg_idx], fields);
             let new_seg_idx = fields.cur_seg_idx - 1;
             // Make sure source text is no longer included in final output
             Self::remove_seg_from_output(source_seg_idx, fields);
             // Update IDs and classes
-            Self::replace_tags_for_segs(&vec![source_seg_idx], &vec![new_seg_idx], fields);
+            Self::replace_tags_for_segs(&[source_seg_idx], &[new_seg_idx], fields);
 
             source_seg_idx = new_seg_idx;
         }
 
         // Optional modifier
         if modifiers.optional {
-            Self::add_basic_incrementer(OptionalModifierIncrementer::new(source_seg_idx), fields);
+            Self::add_basic_incrementer(Box::new(OptionalModifierIncrementer::new(source_seg_idx)), &[source_seg_idx], fields);
             let new_seg_idx = fields.cur_seg_idx - 1;
             // Make sure source text is no longer included in final output
             Self::remove_seg_from_output(source_seg_idx, fields);
             // Update IDs and classes
-            Self::replace_tags_for_segs(&vec![source_seg_idx], &vec![new_seg_idx], fields);
+            Self::replace_tags_for_segs(&[source_seg_idx], &[new_seg_idx], fields);
         }
         
         // Hidden modifier
@@ -218,23 +418,47 @@ impl CandidateGenerator {
         }
     }
 
+    // Moves segments in the output to just before the location anchor, keeping their order
+    fn insert_segs_at_location(source_seg_indices: &[SegIndex], location_seg: SegIndex, fields: &mut CandidateGeneratorFields) -> Result<(), String> {
+        for source_seg in source_seg_indices {
+            // Text that another step used or that is hidden would be output again
+            if !fields.output_indices.contains(source_seg) {
+                return Err("inserted text is no longer part of the output".to_owned());
+            }
+            Self::remove_seg_from_output(*source_seg, fields);
+            let location_pos = fields.output_indices.iter().position(|i| *i == location_seg)
+                .ok_or("location is not part of the output")?;
+            fields.output_indices.insert(location_pos, *source_seg);
+        }
+        Ok(())
+    }
+
     fn remove_seg_from_output(mut source_seg_idx: SegIndex, fields: &mut CandidateGeneratorFields) {
         if let Some(oi) = fields.output_indices.iter().position(|i| *i == source_seg_idx) {
             fields.output_indices.remove(oi);
         }
     }
     
-    fn tag_to_seg_indices(tag: &String, fields: &CandidateGeneratorFields) -> Vec<SegIndex> {
-        if tag.as_bytes()[0] == b'#' {
-            vec![*fields.id_map.get(tag).expect("ERROR: ID doesn't exist")]
-        } else if tag.as_bytes()[0] == b'.' {
-            fields.class_map.get(tag).expect("ERROR: Class doesn't exist").clone()
+    fn tag_to_seg_indices(tag: &String, fields: &CandidateGeneratorFields) -> Result<Vec<SegIndex>, String> {
+        if tag.starts_with('#') {
+            Ok(vec![*fields.id_map.get(tag).ok_or_else(|| format!("ID `{}` doesn't exist", tag))?])
+        } else if tag.starts_with('.') {
+            Ok(fields.class_map.get(tag).ok_or_else(|| format!("class `{}` doesn't exist", tag))?.clone())
         } else {
-            Vec::<SegIndex>::new()
+            Ok(Vec::<SegIndex>::new())
         }
     }
 
-    fn replace_tags_for_segs(source_seg_indices: &Vec<SegIndex>, new_seg_indices: &Vec<SegIndex>, mut fields: &mut CandidateGeneratorFields) {
+    // Segments of a list of #IDs and .classes, in order
+    fn tags_to_seg_indices(tags: &[String], fields: &CandidateGeneratorFields) -> Result<Vec<SegIndex>, String> {
+        let mut seg_indices = Vec::new();
+        for tag in tags {
+            seg_indices.extend(Self::tag_to_seg_indices(tag, fields)?);
+        }
+        Ok(seg_indices)
+    }
+
+    fn replace_tags_for_segs(source_seg_indices: &[SegIndex], new_seg_indices: &[SegIndex], mut fields: &mut CandidateGeneratorFields) {
         assert_eq!(source_seg_indices.len(), new_seg_indices.len());
         for i in 0..source_seg_indices.len() {
             // Find ID that points to source seg and then replace with new seg
@@ -250,35 +474,287 @@ impl CandidateGenerator {
         }
     }
 
-    pub fn print_next(&mut self) -> bool {
+    // Total number of candidates, exact unless a step depends on the generated text
+    pub fn keyspace(&self) -> Keyspace {
+        self.incrementers.iter().map(|inc| inc.keyspace()).product()
+    }
+
+    // Moves ahead to the candidate at this index, returning false if there are not that many candidates
+    pub fn skip_candidates(&mut self, mut n: u128) -> bool {
+        if n == 0 { return true; }
+        self.mark_all_dirty();
+
+        let keyspaces: Vec<Keyspace> = self.incrementers.iter().map(|inc| inc.keyspace()).collect();
+        if !keyspaces.iter().all(Keyspace::is_exact) {
+            // Some steps depend on the generated text, so go through the candidates one by one
+            for _ in 0..n {
+                self.update_segments();
+                if !self.increment() {
+                    self.finished = true;
+                    return false;
+                }
+            }
+            return true;
+        }
+
+        // Split the index into one digit per incrementer, with the last incrementer changing fastest
+        let mut digits = vec![0u128; self.incrementers.len()];
+        for (inc_idx, keyspace) in keyspaces.iter().enumerate().rev() {
+            let size = keyspace.min();
+            if size == 0 {
+                self.finished = true;
+                return false;
+            }
+            digits[inc_idx] = n % size;
+            n /= size;
+        }
+        if n > 0 {
+            self.finished = true;
+            return false;
+        }
+
+        // In order, so that incrementers depending on earlier text see the text they are skipped to
+        for (inc_idx, digit) in digits.into_iter().enumerate() {
+            if !self.incrementers[inc_idx].seek(digit, &self.text_segments) {
+                self.finished = true;
+                return false;
+            }
+            self.update_inc_segments(inc_idx);
+        }
+        true
+    }
+
+    // Splits into about this many units, using as many of the first incrementers as needed, as long as their keyspaces are exact
+    pub fn split_work(&self, target_units: u128) -> WorkSplit {
+        let mut radices = Vec::new();
+        let mut prefixes: u128 = 1;
+        for inc in &self.incrementers {
+            if prefixes >= target_units { break; }
+            let Keyspace::Exact(size) = inc.keyspace() else { break };
+            let Some(new_prefixes) = prefixes.checked_mul(size) else { break };
+            radices.push(size);
+            prefixes = new_prefixes;
+        }
+        WorkSplit { radices, block_size: (prefixes / target_units).max(1) }
+    }
+
+    // Moves to the first candidate of a unit of work, returning false if there is no such unit
+    pub fn seek_unit(&mut self, split: &WorkSplit, unit: u128) -> bool {
+        let prefixes: u128 = split.radices.iter().product();
+        let Some(mut index) = unit.checked_mul(split.block_size).filter(|index| *index < prefixes) else { return false };
+        self.unit_prefixes_left = split.block_size.min(prefixes - index) - 1;
+        self.finished = false;
+        self.mark_all_dirty();
+
+        let mut digits = vec![0u128; split.radices.len()];
+        for (inc_idx, size) in split.radices.iter().enumerate().rev() {
+            digits[inc_idx] = index % size;
+            index /= size;
+        }
+
+        // Later incrementers start over, seeing the text of the unit
+        for inc_idx in 0..self.incrementers.len() {
+            match digits.get(inc_idx) {
+                Some(digit) => if !self.incrementers[inc_idx].seek(*digit, &self.text_segments) { return false; },
+                None => self.incrementers[inc_idx].reset(&self.text_segments)
+            }
+            self.update_inc_segments(inc_idx);
+        }
+        true
+    }
+
+    // Stops after this many more candidates have been printed
+    pub fn set_limit(&mut self, limit: u128) {
+        self.limit = Some(limit);
+    }
+
+    // Progress so far; restoring this continues with the candidate that would be printed next
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            incrementer_states: self.incrementers.iter().map(|inc| inc.save_state()).collect(),
+            limit: self.limit,
+            finished: self.finished,
+            fingerprint: self.fingerprint
+        }
+    }
+
+    pub fn restore(&mut self, checkpoint: &Checkpoint) -> Result<(), String> {
+        if checkpoint.fingerprint != self.fingerprint {
+            return Err("checkpoint was saved for a different recipe, or its wordlists have changed since".to_owned());
+        }
+        if checkpoint.incrementer_states.len() != self.incrementers.len() {
+            return Err(format!("checkpoint has {} incrementers but the recipe has {}",
+                               checkpoint.incrementer_states.len(), self.incrementers.len()));
+        }
+        self.mark_all_dirty();
+        // Restore in order so that incrementers depending on earlier text see the restored text
+        for inc_idx in 0..self.incrementers.len() {
+            if !self.incrementers[inc_idx].restore_state(&checkpoint.incrementer_states[inc_idx], &self.text_segments) {
+                return Err(format!("checkpoint state for incrementer {} doesn't match the recipe", inc_idx + 1));
+            }
+            self.update_inc_segments(inc_idx);
+        }
+        self.limit = checkpoint.limit;
+        self.finished = checkpoint.finished;
+        Ok(())
+    }
+
+    // The next candidate, or None once they have all been returned, the limit is reached or there was an error
+    pub fn next_candidate(&mut self) -> Option<&str> {
+        if !self.start_candidate() { return None; }
+        // After an error the remaining candidates are unknown, so it isn't finished
+        self.finished = !self.increment() && self.error.is_none();
+        Some(&self.buffer)
+    }
+
+    // Like next_candidate, but stops at the end of the current unit of work
+    pub fn next_candidate_in_unit(&mut self, split: &WorkSplit) -> Option<&str> {
+        let prefix_len = split.radices.len();
+        if !self.start_candidate() { return None; }
+        if !self.increment_range(prefix_len, self.incrementers.len()) {
+            if self.unit_prefixes_left == 0 {
+                self.finished = true;
+            } else {
+                self.unit_prefixes_left -= 1;
+                self.finished = !self.increment_range(0, prefix_len);
+            }
+            self.finished &= self.error.is_none();
+        }
+        Some(&self.buffer)
+    }
+
+    // Copy in the same state for another thread, sharing loaded wordlists and rules with this generator
+    pub fn fork(&self) -> std::io::Result<CandidateGenerator> {
+        Ok(CandidateGenerator {
+            incrementers: self.incrementers.iter().map(|inc| inc.fork()).collect::<std::io::Result<_>>()?,
+            write_indices: self.write_indices.clone(),
+            dependents: self.dependents.clone(),
+            text_segments: self.text_segments.clone(),
+            output_indices: self.output_indices.clone(),
+            dirty: self.dirty.clone(),
+            first_dirty: self.first_dirty,
+            advanced: self.advanced.clone(),
+            buffer: self.buffer.clone(),
+            finished: self.finished,
+            error: self.error.as_ref().map(|e| std::io::Error::new(e.kind(), e.to_string())),
+            limit: self.limit,
+            custom_charsets: self.custom_charsets.clone(),
+            warnings: self.warnings.clone(),
+            fingerprint: self.fingerprint,
+            unit_prefixes_left: self.unit_prefixes_left
+        })
+    }
+
+    // Problems with the recipe's files that were worked around, e.g. rules that were skipped, for the caller to show
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    // Why generating stopped before every candidate was returned, if it did
+    pub fn error(&self) -> Option<&std::io::Error> {
+        self.error.as_ref()
+    }
+
+    // Writes the next candidate as a line, returning false when there are none left
+    pub fn write_next<W: Write + ?Sized>(&mut self, writer: &mut W) -> std::io::Result<bool> {
+        match self.next_candidate() {
+            Some(candidate) => writeln!(writer, "{}", candidate).map(|_| true),
+            None => self.stopped()
+        }
+    }
+
+    pub fn write_next_in_unit<W: Write + ?Sized>(&mut self, split: &WorkSplit, writer: &mut W) -> std::io::Result<bool> {
+        match self.next_candidate_in_unit(split) {
+            Some(candidate) => writeln!(writer, "{}", candidate).map(|_| true),
+            None => self.stopped()
+        }
+    }
+
+    fn stopped(&self) -> std::io::Result<bool> {
+        match &self.error {
+            Some(e) => Err(std::io::Error::new(e.kind(), e.to_string())),
+            None => Ok(false)
+        }
+    }
+
+    // Fills the buffer with the current candidate, unless finished or out of the limit
+    fn start_candidate(&mut self) -> bool {
+        if self.finished || self.error.is_some() { return false; }
+        if let Some(limit) = &mut self.limit {
+            if *limit == 0 { return false; }
+            *limit -= 1;
+        }
         self.update_buffer();
-        writeln!(self.writer, "{}", self.buffer);
-        self.increment()
+        true
     }
 
     fn increment(&mut self) -> bool {
-        for inc in self.incrementers.iter_mut().rev() {
-            let inc_success = inc.increment(&self.text_segments);
+        self.increment_range(0, self.incrementers.len())
+    }
 
-            if inc_success { return true; }
-            else { inc.reset(&self.text_segments); }
+    // Only changes the incrementers from first_inc up to before end_inc
+    fn increment_range(&mut self, first_inc: IncIndex, end_inc: IncIndex) -> bool {
+        for inc_idx in (first_inc..end_inc).rev() {
+            if self.incrementers[inc_idx].increment(&self.text_segments) {
+                self.advanced[inc_idx] = true;
+                self.mark_dirty(inc_idx);
+                return true;
+            }
+            self.incrementers[inc_idx].reset(&self.text_segments);
+            if let Some(error) = self.incrementers[inc_idx].take_error() {
+                self.error = Some(error);
+                return false;
+            }
+            // Resetting an incrementer that is still on its first entry doesn't change it, e.g. a constant
+            if mem::take(&mut self.advanced[inc_idx]) { self.mark_dirty(inc_idx); }
         }
         false
     }
 
+    fn mark_dirty(&mut self, inc_idx: IncIndex) {
+        self.dirty[inc_idx] = true;
+        for dependent in &self.dependents[inc_idx] {
+            self.dirty[*dependent] = true;
+        }
+        self.first_dirty = self.first_dirty.min(inc_idx);
+    }
+
+    // After seeking, any incrementer can be anywhere
+    fn mark_all_dirty(&mut self) {
+        self.dirty.fill(true);
+        self.advanced.fill(true);
+        self.first_dirty = 0;
+    }
+
+    // Only recomputes the segments of the incrementers that changed and the incrementers that read their text
+    fn update_segments(&mut self) {
+        for inc_idx in self.first_dirty..self.incrementers.len() {
+            if mem::take(&mut self.dirty[inc_idx]) { self.update_inc_segments(inc_idx); }
+        }
+        self.first_dirty = self.incrementers.len();
+    }
+
+    fn update_inc_segments(&mut self, inc_idx: IncIndex) {
+        // An incrementer only reads segments written by earlier incrementers, which come before its own
+        let write_range = &self.write_indices[inc_idx];
+        let (text_segments, outputs) = self.text_segments.split_at_mut(write_range.start);
+        self.incrementers[inc_idx].output(text_segments, &mut outputs[..write_range.len()]);
+    }
+
     fn update_buffer(&mut self) {
         self.buffer.clear();
-        // Update text_segments
-        for inc_idx in 0..self.incrementers.len() {
-            let inc = &self.incrementers[inc_idx];
-            let outputs = inc.output(&self.text_segments);
-            for i in 0usize..self.write_indices[inc_idx].len() {
-                self.text_segments[self.write_indices[inc_idx][i]] = outputs[i].clone();
-            }
-        }
+        self.update_segments();
         // Update output buffer
         for outseg_idx in self.output_indices.iter() {
-            self.buffer.push_str(self.text_segments[*outseg_idx].as_str());
+            self.buffer.push_str(&self.text_segments[*outseg_idx]);
         }
     }
 }
+
+impl Iterator for CandidateGenerator {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.next_candidate().map(str::to_owned)
+    }
+}
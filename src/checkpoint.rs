@@ -0,0 +1,117 @@
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+use std::time::UNIX_EPOCH;
+
+// State of a single incrementer, as a list of numbers that only the incrementer itself needs to understand
+pub type IncrementerState = Vec<u64>;
+
+// Saved progress of a CandidateGenerator; restoring it continues from the next candidate that wasn't printed
+#[derive(Debug, Default, PartialEq)]
+pub struct Checkpoint {
+    pub incrementer_states: Vec<IncrementerState>,
+    pub limit: Option<u128>,
+    pub finished: bool,
+    pub fingerprint: u64                // Of the recipe and wordlists it was saved for
+}
+
+// Identifies a recipe and the size and modification time of its wordlists, so a checkpoint isn't restored for
+// different ones; FNV-1a, since it has to stay the same between builds
+#[derive(Debug, Clone, Copy)]
+pub struct Fingerprint(u64);
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Fingerprint(0xcbf29ce484222325)
+    }
+}
+
+impl Fingerprint {
+    pub fn add(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub fn add_file(&mut self, path: &str) -> Result<(), String> {
+        let metadata = fs::metadata(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+        let modified = metadata.modified().ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_nanos());
+        self.add(path.as_bytes());
+        self.add(&metadata.len().to_le_bytes());
+        self.add(&modified.to_le_bytes());
+        Ok(())
+    }
+
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+const HEADER: &str = "# PasswordChef checkpoint";
+
+impl Checkpoint {
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\nfingerprint {:016x}\nincrementers {}\n", HEADER, self.fingerprint, self.incrementer_states.len());
+        if let Some(limit) = self.limit {
+            writeln!(text, "limit {}", limit).unwrap();
+        }
+        if self.finished {
+            text.push_str("finished\n");
+        }
+        for state in &self.incrementer_states {
+            text.push_str("state");
+            for value in state {
+                write!(text, " {}", value).unwrap();
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Checkpoint, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err("not a PasswordChef checkpoint file".to_owned());
+        }
+
+        let mut checkpoint = Checkpoint::default();
+        let mut num_incrementers = None;
+        let mut fingerprint = None;
+        for line in lines {
+            let mut tokens = line.split_whitespace();
+            let parse_err = |e: std::num::ParseIntError| format!("invalid number in checkpoint line `{}`: {}", line, e);
+            match tokens.next() {
+                Some("fingerprint") => fingerprint = Some(u64::from_str_radix(tokens.next().unwrap_or(""), 16).map_err(parse_err)?),
+                Some("incrementers") => num_incrementers = Some(tokens.next().unwrap_or("").parse::<usize>().map_err(parse_err)?),
+                Some("limit") => checkpoint.limit = Some(tokens.next().unwrap_or("").parse::<u128>().map_err(parse_err)?),
+                Some("finished") => checkpoint.finished = true,
+                Some("state") => checkpoint.incrementer_states.push(
+                    tokens.map(str::parse::<u64>).collect::<Result<_, _>>().map_err(parse_err)?
+                ),
+                Some(other) => return Err(format!("unknown checkpoint entry `{}`", other)),
+                None => {}
+            }
+        }
+
+        if num_incrementers != Some(checkpoint.incrementer_states.len()) {
+            return Err("checkpoint file is incomplete".to_owned());
+        }
+        // Checkpoints from before fingerprints were added can't be checked
+        checkpoint.fingerprint = fingerprint.ok_or("checkpoint file has no fingerprint; it was saved by an older version")?;
+        Ok(checkpoint)
+    }
+
+    // Writes to a temporary file first so an interrupted write can't corrupt the previous checkpoint
+    pub fn write_to(&self, path: &str) -> io::Result<()> {
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, self.to_text())?;
+        fs::rename(&tmp_path, path)
+    }
+
+    pub fn read_from(path: &str) -> io::Result<Checkpoint> {
+        let text = fs::read_to_string(path)?;
+        Checkpoint::from_text(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
@@ -0,0 +1,98 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use crate::candidate_generator::{GeneratorOptions, SegIndex};
+use crate::items::incrementer_trait::RecipeIncrementer;
+use crate::items::mask::CustomCharsets;
+
+// Parsed arguments of a custom step, of whatever type its argument parser returns
+pub type CustomStepArgs = Arc<dyn Any + Send + Sync>;
+
+type ArgParser = dyn Fn(&str, &GeneratorOptions) -> Result<CustomStepArgs, String> + Send + Sync;
+type Factory = dyn Fn(&CustomStepArgs, &CustomStepContext) -> Box<dyn RecipeIncrementer> + Send + Sync;
+
+// What a custom step's factory gets besides its arguments
+pub struct CustomStepContext<'a> {
+    pub target_seg: Option<SegIndex>,           // Text segment of the target, for steps registered with a target
+    pub custom_charsets: &'a CustomCharsets     // After the charset steps before this one
+}
+
+// A step type added by a library user; modifiers, IDs and classes work the same as for built-in steps
+#[derive(Clone)]
+pub struct CustomStep {
+    pub name: &'static str,
+    pub takes_target: bool,                     // Written as `name #target arguments` and reads the target's text, like rules
+    parse_args: Arc<ArgParser>,
+    build: Arc<Factory>
+}
+
+impl CustomStep {
+    // Arguments are the rest of the step after the name (and target), which can be empty
+    pub fn parse_args(&self, args: &str, options: &GeneratorOptions) -> Result<CustomStepArgs, String> {
+        (self.parse_args)(args, options)
+    }
+
+    pub fn build(&self, args: &CustomStepArgs, context: &CustomStepContext) -> Box<dyn RecipeIncrementer> {
+        (self.build)(args, context)
+    }
+}
+
+impl Debug for CustomStep {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CustomStep({})", self.name)
+    }
+}
+
+// Custom steps by name; the recipe parser checks these before the built-in step types, so they can replace them
+#[derive(Clone, Default)]
+pub struct StepRegistry {
+    steps: HashMap<&'static str, CustomStep>
+}
+
+impl StepRegistry {
+    // Step that generates its own text, written as `name arguments`
+    // The argument parser can use the options to find files; errors are reported like other recipe errors
+    pub fn register<A, P, F>(&mut self, name: &'static str, parse_args: P, build: F)
+    where A: Send + Sync + 'static,
+          P: Fn(&str, &GeneratorOptions) -> Result<A, String> + Send + Sync + 'static,
+          F: Fn(&A, &CustomStepContext) -> Box<dyn RecipeIncrementer> + Send + Sync + 'static {
+        self.add(name, false, parse_args, build);
+    }
+
+    // Step that changes the text of an earlier step, written as `name #target arguments`
+    // The target's segment is given in the context, and the target is no longer output by itself
+    pub fn register_with_target<A, P, F>(&mut self, name: &'static str, parse_args: P, build: F)
+    where A: Send + Sync + 'static,
+          P: Fn(&str, &GeneratorOptions) -> Result<A, String> + Send + Sync + 'static,
+          F: Fn(&A, &CustomStepContext) -> Box<dyn RecipeIncrementer> + Send + Sync + 'static {
+        self.add(name, true, parse_args, build);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CustomStep> {
+        self.steps.get(name)
+    }
+
+    fn add<A, P, F>(&mut self, name: &'static str, takes_target: bool, parse_args: P, build: F)
+    where A: Send + Sync + 'static,
+          P: Fn(&str, &GeneratorOptions) -> Result<A, String> + Send + Sync + 'static,
+          F: Fn(&A, &CustomStepContext) -> Box<dyn RecipeIncrementer> + Send + Sync + 'static {
+        let step = CustomStep {
+            name,
+            takes_target,
+            parse_args: Arc::new(move |args, options| parse_args(args, options).map(|args| Arc::new(args) as CustomStepArgs)),
+            build: Arc::new(move |args, context| {
+                // Arguments always come from this step's own parser
+                let args = (**args).downcast_ref::<A>().expect("ERROR: custom step arguments have the wrong type");
+                build(args, context)
+            })
+        };
+        self.steps.insert(name, step);
+    }
+}
+
+impl Debug for StepRegistry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.steps.keys()).finish()
+    }
+}
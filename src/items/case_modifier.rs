@@ -1,17 +1,17 @@
 use std::cell::RefCell;
 use std::rc::Rc;
-use flexstr::{LocalStr, ToLocalStr};
-use smallvec::{smallvec, SmallVec};
+use std::io;
 use strum::IntoEnumIterator;
-use unicode_titlecase::StrTitleCase;
-use crate::candidate_generator::SV_SIZE;
+use unicode_titlecase::TitleCase as _;
 use crate::items::incrementer_trait::RecipeIncrementer;
+use crate::keyspace::Keyspace;
+use crate::checkpoint::IncrementerState;
 use crate::recipe_step::CaseModifierType::{Lowercase, OriginalCase};
 use crate::recipe_step::{CaseModifierType, CaseModifiers, GeneratorModifiers};
 
 use crate::recipe_step::CaseModifierType::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CaseModifierIncrementer {
     source_seg_idx: usize,
     modifiers: CaseModifiers,
@@ -37,29 +37,68 @@ impl CaseModifierIncrementer {
 }
 
 impl RecipeIncrementer for CaseModifierIncrementer {
-    fn increment(&mut self, text_segments: &Vec<LocalStr>) -> bool {
+    fn increment(&mut self, text_segments: &[String]) -> bool {
         self.cur_case += 1;
         let max_case = CaseModifierType::iter().len()-1;
-        while (self.cur_case <= max_case && !self.modifiers.includes_case(CaseModifierType::iter().nth(self.cur_case).unwrap())) {
+        while (self.cur_case <= max_case && !self.modifiers.includes_case(&CaseModifierType::iter().nth(self.cur_case).unwrap())) {
             self.cur_case += 1;
         }
         self.cur_case <= max_case
     }
 
-    fn reset(&mut self, text_segments: &Vec<LocalStr>) {
+    fn reset(&mut self, text_segments: &[String]) {
         self.cur_case = self.start_case;
     }
 
-    fn output(&self, text_segments: &Vec<LocalStr>) -> SmallVec<[LocalStr; SV_SIZE]> {
+    fn keyspace(&self) -> Keyspace {
+        Keyspace::Exact(CaseModifierType::iter().filter(|c| self.modifiers.includes_case(c)).count().max(1) as u128)
+    }
+
+    fn seek(&mut self, index: u128, text_segments: &[String]) -> bool {
+        let case_num = CaseModifierType::iter()
+            .enumerate()
+            .filter(|(_, c)| self.modifiers.includes_case(c))
+            .nth(usize::try_from(index).unwrap_or(usize::MAX))
+            .map(|(i, _)| i);
+        match case_num {
+            Some(i) => { self.cur_case = i; true }
+            None => { self.reset(text_segments); index == 0 }
+        }
+    }
+
+    fn save_state(&self) -> IncrementerState {
+        vec![self.cur_case as u64]
+    }
+
+    fn restore_state(&mut self, state: &[u64], text_segments: &[String]) -> bool {
+        match state {
+            [cur_case] if CaseModifierType::iter().nth(*cur_case as usize).is_some_and(|c| self.modifiers.includes_case(&c)) => {
+                self.cur_case = *cur_case as usize;
+                true
+            }
+            _ => false
+        }
+    }
+
+    fn output(&self, text_segments: &[String], outputs: &mut [String]) {
         let case = CaseModifierType::iter().nth(self.cur_case).unwrap();
-        let text = text_segments[self.source_seg_idx].clone();
-        smallvec![
-            match case {
-                OriginalCase => text,
-                Lowercase => text.to_ascii_lowercase().to_local_str(),
-                Uppercase => text.to_ascii_uppercase().to_local_str(),
-                TitleCase => text.to_titlecase_lower_rest().to_local_str()
+        let text = &text_segments[self.source_seg_idx];
+        let output = &mut outputs[0];
+        output.clear();
+        match case {
+            OriginalCase => output.push_str(text),
+            Lowercase => { output.push_str(text); output.make_ascii_lowercase() }
+            Uppercase => { output.push_str(text); output.make_ascii_uppercase() }
+            // Same as to_titlecase_lower_rest, without allocating a new string
+            TitleCase => {
+                let mut chars = text.chars();
+                output.extend(chars.next().into_iter().flat_map(|c| c.to_titlecase()));
+                output.extend(chars.flat_map(char::to_lowercase));
             }
-        ]
+        }
+    }
+
+    fn fork(&self) -> io::Result<Box<dyn RecipeIncrementer>> {
+        Ok(Box::new(self.clone()))
     }
 }
\ No newline at end of file
@@ -1,11 +1,11 @@
 use std::ops::Range;
-use flexstr::{LocalStr, ToLocalStr};
+use std::io;
 use itertools::{Group, Itertools, Permutations};
-use smallvec::{smallvec, SmallVec};
-use crate::candidate_generator::{SegIndex, SV_SIZE};
+use crate::candidate_generator::SegIndex;
 use crate::items::incrementer_trait::RecipeIncrementer;
+use crate::keyspace::Keyspace;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConcatIncrementer {
     source_id_indices: Vec<SegIndex>
 }
@@ -18,15 +18,21 @@ impl ConcatIncrementer {
 }
 
 impl RecipeIncrementer for ConcatIncrementer {
-    fn increment(&mut self, text_segments: &Vec<LocalStr>) -> bool {
+    fn increment(&mut self, text_segments: &[String]) -> bool {
         false
     }
-    fn output(&self, text_segments: &Vec<LocalStr>) -> SmallVec<[LocalStr; SV_SIZE]> {
-        smallvec![
-            self.source_id_indices.iter()
-                .map(|src_id| text_segments[*src_id].clone())
-                .join("")
-                .to_local_str()
-        ]
+    fn keyspace(&self) -> Keyspace {
+        Keyspace::Exact(1)
+    }
+
+    fn output(&self, text_segments: &[String], outputs: &mut [String]) {
+        outputs[0].clear();
+        for src_id in &self.source_id_indices {
+            outputs[0].push_str(&text_segments[*src_id]);
+        }
+    }
+
+    fn fork(&self) -> io::Result<Box<dyn RecipeIncrementer>> {
+        Ok(Box::new(self.clone()))
     }
 }
\ No newline at end of file
@@ -1,25 +1,33 @@
-use flexstr::{LocalStr, ToLocalStr};
-use smallvec::{smallvec, SmallVec};
-use crate::candidate_generator::SV_SIZE;
+use std::io;
 use crate::items::incrementer_trait::RecipeIncrementer;
+use crate::keyspace::Keyspace;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConstantIncrementer {
-    pub value: LocalStr
+    pub value: String
 }
 
 impl ConstantIncrementer {
     pub fn new(value: String) -> Self {
-        Self { value: value.to_local_str() }
+        Self { value }
     }
 }
 
 impl RecipeIncrementer for ConstantIncrementer {
-    fn increment(&mut self, text_segments: &Vec<LocalStr>) -> bool {
+    fn increment(&mut self, text_segments: &[String]) -> bool {
         false
     }
 
-    fn output(&self, text_segments: &Vec<LocalStr>) -> SmallVec<[LocalStr; SV_SIZE]> {
-        smallvec![self.value.clone()]
+    fn keyspace(&self) -> Keyspace {
+        Keyspace::Exact(1)
+    }
+
+    fn output(&self, text_segments: &[String], outputs: &mut [String]) {
+        outputs[0].clear();
+        outputs[0].push_str(&self.value);
+    }
+
+    fn fork(&self) -> io::Result<Box<dyn RecipeIncrementer>> {
+        Ok(Box::new(self.clone()))
     }
 }
\ No newline at end of file
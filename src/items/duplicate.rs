@@ -1,9 +1,9 @@
-use flexstr::LocalStr;
-use smallvec::{smallvec, SmallVec};
-use crate::candidate_generator::{SegIndex, SV_SIZE};
+use std::io;
+use crate::candidate_generator::SegIndex;
 use crate::items::incrementer_trait::RecipeIncrementer;
+use crate::keyspace::Keyspace;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DuplicateIncrementer {
     source_seg_idx: SegIndex
 }
@@ -15,11 +15,20 @@ impl DuplicateIncrementer {
 }
 
 impl RecipeIncrementer for DuplicateIncrementer {
-    fn increment(&mut self, text_segments: &Vec<LocalStr>) -> bool {
+    fn increment(&mut self, text_segments: &[String]) -> bool {
         false
     }
 
-    fn output(&self, text_segments: &Vec<LocalStr>) -> SmallVec<[LocalStr; SV_SIZE]> {
-        smallvec![text_segments[self.source_seg_idx].clone()]
+    fn keyspace(&self) -> Keyspace {
+        Keyspace::Exact(1)
+    }
+
+    fn output(&self, text_segments: &[String], outputs: &mut [String]) {
+        outputs[0].clear();
+        outputs[0].push_str(&text_segments[self.source_seg_idx]);
+    }
+
+    fn fork(&self) -> io::Result<Box<dyn RecipeIncrementer>> {
+        Ok(Box::new(self.clone()))
     }
 }
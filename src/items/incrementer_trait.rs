@@ -1,14 +1,35 @@
 use std::fmt::Debug;
-use flexstr::LocalStr;
-use smallvec::SmallVec;
-use crate::candidate_generator::SV_SIZE;
+use std::io;
+use crate::keyspace::Keyspace;
+use crate::checkpoint::IncrementerState;
 
 // A RecipeItem is like an iterator but is able to reset
 // RecipeItems are called in order of recipe steps and are responsible for looping through permutations of a step
-pub trait RecipeIncrementer {
-    fn increment(&mut self, text_segments: &Vec<LocalStr>) -> bool;  // new items should start on first entry, so only increment aftewards
-    fn reset(&mut self, text_segments: &Vec<LocalStr>) {}                                             // will reset to first entry
-    fn output(&self, text_segments: &Vec<LocalStr>) -> SmallVec<[LocalStr; SV_SIZE]>;
-}
+// They are Send so that copies can be made for other threads
+pub trait RecipeIncrementer: Send {
+    fn increment(&mut self, text_segments: &[String]) -> bool;  // new items should start on first entry, so only increment aftewards
+    fn reset(&mut self, text_segments: &[String]) {}                                             // will reset to first entry
+    // Writes the current entry into this incrementer's output segments, which still hold its last output so their
+    // allocations can be reused; text_segments are the segments before them, which are the only ones it can read
+    fn output(&self, text_segments: &[String], outputs: &mut [String]);
+    fn keyspace(&self) -> Keyspace;                                                                   // number of entries from reset until increment fails
+
+    // Moves to the entry at this index (counting from the first entry), returning false if it doesn't exist
+    // Incrementers whose state can be computed from an index should override this to jump straight there
+    fn seek(&mut self, index: u128, text_segments: &[String]) -> bool {
+        self.reset(text_segments);
+        (0..index).all(|_| self.increment(text_segments))
+    }
 
+    // Used for checkpoints; incrementers without any state can keep the defaults
+    fn save_state(&self) -> IncrementerState { Vec::new() }
+    fn restore_state(&mut self, state: &[u64], text_segments: &[String]) -> bool { state.is_empty() }
 
+    // An error that ended this incrementer's entries early, e.g. a file that couldn't be read
+    // Checked after increment fails and the incrementer is reset
+    fn take_error(&mut self) -> Option<io::Error> { None }
+
+    // Copy in the same state for another thread; data that doesn't change, like a loaded wordlist or rule file,
+    // should be shared with an Arc instead of copied, so that only the position is kept per thread
+    fn fork(&self) -> io::Result<Box<dyn RecipeIncrementer>>;
+}
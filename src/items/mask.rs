@@ -1,8 +1,8 @@
-use smallvec::{smallvec, SmallVec};
+use std::io;
 use constcat::concat;
-use flexstr::{LocalStr, ToLocalStr};
-use crate::candidate_generator::SV_SIZE;
 use crate::items::incrementer_trait::RecipeIncrementer;
+use crate::keyspace::Keyspace;
+use crate::checkpoint::IncrementerState;
 use MaskCharType::*;
 
 const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
@@ -12,6 +12,17 @@ const SPECIAL_ALL: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~ ";
 const SPECIAL_SUBSET: &str = "!@#$%^&*+-=_";
 const HEXLETTERS_LOWER: &str = "abcdef";
 const HEXLETTERS_UPPER: &str = "ABCDEF";
+const GERMAN_LOWER: &str = "äöüß";
+const GERMAN_UPPER: &str = "ÄÖÜ";
+const POLISH_LOWER: &str = "ąćęłńóśźż";
+const POLISH_UPPER: &str = "ĄĆĘŁŃÓŚŹŻ";
+const CYRILLIC_LOWER: &str = "абвгдеёжзийклмнопрстуфхцчшщъыьэюя";
+const CYRILLIC_UPPER: &str = "АБВГДЕЁЖЗИЙКЛМНОПРСТУФХЦЧШЩЪЫЬЭЮЯ";
+
+pub const NUM_CUSTOM_CHARSETS: usize = 4;
+
+// User-defined charsets for ?1 to ?4, empty if not defined
+pub type CustomCharsets = [String; NUM_CUSTOM_CHARSETS];
 
 #[derive(Debug)]
 pub enum MaskCharType {
@@ -26,11 +37,17 @@ pub enum MaskCharType {
     EverythingAlmost,
     HexLower,
     HexUpper,
-    Custom1
+    GermanLower,
+    GermanUpper,
+    PolishLower,
+    PolishUpper,
+    CyrillicLower,
+    CyrillicUpper,
+    Custom(usize)   // Index into CustomCharsets
 }
 
 impl MaskCharType {
-    fn mask_type_from_letter(char: &u8) -> Option<MaskCharType> {
+    pub fn mask_type_from_letter(char: &u8) -> Option<MaskCharType> {
         match char {
             b'l' => Some(Lowercase),
             b'u' => Some(Uppercase),
@@ -43,11 +60,17 @@ impl MaskCharType {
             b'E' => Some(EverythingAlmost),
             b'h' => Some(HexLower),
             b'H' => Some(HexUpper),
-            b'1' => Some(Custom1),
+            b'g' => Some(GermanLower),
+            b'G' => Some(GermanUpper),
+            b'p' => Some(PolishLower),
+            b'P' => Some(PolishUpper),
+            b'c' => Some(CyrillicLower),
+            b'C' => Some(CyrillicUpper),
+            b'1'..=b'4' => Some(Custom((char - b'1') as usize)),
             _ => None
         }
     }
-    fn charset(mask_char_type: &MaskCharType) -> &'static str {
+    fn charset<'a>(mask_char_type: &MaskCharType, custom_charsets: &'a CustomCharsets) -> &'a str {
         match mask_char_type {
             Lowercase => LOWERCASE,
             Uppercase => UPPERCASE,
@@ -60,32 +83,108 @@ impl MaskCharType {
             EverythingAlmost => concat!(LOWERCASE, UPPERCASE, DIGITS, SPECIAL_SUBSET),
             HexLower => concat!(DIGITS, HEXLETTERS_LOWER),
             HexUpper => concat!(DIGITS, HEXLETTERS_UPPER),
-            Custom1 => ""
+            GermanLower => concat!(LOWERCASE, GERMAN_LOWER),
+            GermanUpper => concat!(UPPERCASE, GERMAN_UPPER),
+            PolishLower => concat!(LOWERCASE, POLISH_LOWER),
+            PolishUpper => concat!(UPPERCASE, POLISH_UPPER),
+            CyrillicLower => CYRILLIC_LOWER,
+            CyrillicUpper => CYRILLIC_UPPER,
+            Custom(i) => &custom_charsets[*i]
+        }
+    }
+
+    fn charset_checked<'a>(mask_char_type: &MaskCharType, custom_charsets: &'a CustomCharsets) -> Result<&'a str, String> {
+        match mask_char_type {
+            Custom(i) if custom_charsets[*i].is_empty() => Err(format!("custom charset `?{}` is not defined", i + 1)),
+            _ => Ok(MaskCharType::charset(mask_char_type, custom_charsets))
         }
     }
+
+    // Expands a charset definition like `?l?d!` into its characters, without duplicates
+    pub fn expand_charset(definition: &str, custom_charsets: &CustomCharsets) -> Result<String, String> {
+        let mut charset = String::new();
+        for token in MaskToken::tokenize(definition)? {
+            let expanded = match token {
+                MaskToken::Literal(c) => c.to_string(),
+                MaskToken::Class(t) => MaskCharType::charset_checked(&t, custom_charsets)?.to_owned()
+            };
+            for e in expanded.chars() {
+                if !charset.contains(e) { charset.push(e); }
+            }
+        }
+        Ok(charset)
+    }
+
+    // Gets the characters for each position of a mask
+    // Masks containing `?` are hashcat-style (`Summer?d?d!`), otherwise every character is a charset letter (`ulld`)
+    pub fn parse_mask(mask: &str, custom_charsets: &CustomCharsets) -> Result<Vec<Vec<char>>, String> {
+        let tokens = if mask.contains('?') {
+            MaskToken::tokenize(mask)?
+        } else {
+            mask.chars()
+                .map(|c| MaskCharType::from_letter(c).map(MaskToken::Class))
+                .collect::<Result<_, _>>()?
+        };
+        tokens.into_iter()
+            .map(|token| match token {
+                MaskToken::Literal(c) => Ok(vec![c]),
+                MaskToken::Class(t) => MaskCharType::charset_checked(&t, custom_charsets).map(|s| s.chars().collect())
+            })
+            .collect()
+    }
+
+    fn from_letter(letter: char) -> Result<MaskCharType, String> {
+        u8::try_from(letter).ok()
+            .and_then(|b| MaskCharType::mask_type_from_letter(&b))
+            .ok_or_else(|| format!("invalid mask character `{}`", letter))
+    }
 }
 
-#[derive(Debug)]
+enum MaskToken {
+    Literal(char),
+    Class(MaskCharType)
+}
+
+impl MaskToken {
+    // `?x` is the built-in or custom charset x, `??` is a literal `?`, anything else is literal
+    fn tokenize(text: &str) -> Result<Vec<MaskToken>, String> {
+        let mut tokens = Vec::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                '?' => match chars.next() {
+                    Some('?') => MaskToken::Literal('?'),
+                    Some(letter) => MaskToken::Class(MaskCharType::from_letter(letter)
+                        .map_err(|_| format!("unknown charset `?{}`", letter))?),
+                    None => return Err("`?` at the end must be written as `??`".to_owned())
+                },
+                c => MaskToken::Literal(c)
+            });
+        }
+        Ok(tokens)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct MaskIncrementer {
-    char_type: Vec<MaskCharType>,
+    charsets: Vec<Vec<char>>,   // Characters to go through for each position in the mask
     char_idx: Vec<usize>,
     char_max: Vec<usize>  // Maximum index for each character in the mask (exclusive)
 }
 
 impl MaskIncrementer {
-    pub fn new(mask: String) -> Self {
-        let char_type: Vec<MaskCharType> = mask.as_bytes().iter()
-            .map(|c| MaskCharType::mask_type_from_letter(c)
-                                   .unwrap_or_else(|| panic!("Invalid mask character: {}", *c as char)))
-            .collect();
-        let char_max: Vec<usize> = char_type.iter().map(|t| MaskCharType::charset(t).len()).collect();
-        Self { char_type, char_idx: vec![0; char_max.len()], char_max }
+    pub fn new(mask: String, custom_charsets: &CustomCharsets) -> Result<Self, String> {
+        let charsets = MaskCharType::parse_mask(&mask, custom_charsets)
+            .map_err(|e| format!("{} in mask `{}`", e, mask))?;
+        let char_max: Vec<usize> = charsets.iter().map(Vec::len).collect();
+        Ok(Self { charsets, char_idx: vec![0; char_max.len()], char_max })
     }
 }
 
-impl RecipeIncrementer for MaskIncrementer {
-    fn increment(&mut self, text_segments: &Vec<LocalStr>) -> bool {
-        for i in (0..self.char_idx.len()).rev() {
+impl MaskIncrementer {
+    // Increments only the first `len` characters of the mask
+    fn increment_prefix(&mut self, len: usize) -> bool {
+        for i in (0..len).rev() {
             self.char_idx[i] += 1;
 
             if self.char_idx[i] < self.char_max[i] { return true; }
@@ -94,13 +193,159 @@ impl RecipeIncrementer for MaskIncrementer {
         false
     }
 
-    fn output(&self, text_segments: &Vec<LocalStr>) -> SmallVec<[LocalStr; SV_SIZE]> {
-        smallvec![String::from_utf8(
-            self.char_idx
-                .iter()
-                .enumerate()
-                .map(|(i, ci)| MaskCharType::charset(&self.char_type[i]).as_bytes()[*ci])
-                .collect()
-        ).unwrap().to_local_str()]
+    fn output_prefix(&self, len: usize, output: &mut String) {
+        output.clear();
+        output.extend(self.char_idx[..len]
+            .iter()
+            .enumerate()
+            .map(|(i, ci)| self.charsets[i][*ci]));
+    }
+
+    fn reset_idx(&mut self) {
+        self.char_idx.fill(0);
+    }
+
+    fn len(&self) -> usize {
+        self.char_idx.len()
+    }
+
+    // Sets the first `len` characters from an index, with the last character changing fastest
+    fn seek_prefix(&mut self, mut index: u128, len: usize) -> bool {
+        self.reset_idx();
+        for i in (0..len).rev() {
+            let char_max = self.char_max[i] as u128;
+            if char_max == 0 { return false; }
+            self.char_idx[i] = (index % char_max) as usize;
+            index /= char_max;
+        }
+        index == 0
+    }
+
+    fn restore_idx(&mut self, state: &[u64]) -> bool {
+        if state.len() != self.len() || state.iter().zip(&self.char_max).any(|(ci, max)| *ci as usize >= *max) {
+            return false;
+        }
+        for (ci, s) in self.char_idx.iter_mut().zip(state) { *ci = *s as usize; }
+        true
+    }
+
+    fn prefix_keyspace(&self, len: usize) -> Keyspace {
+        self.char_max[..len].iter().map(|m| Keyspace::Exact(*m as u128)).product()
+    }
+}
+
+impl RecipeIncrementer for MaskIncrementer {
+    fn increment(&mut self, text_segments: &[String]) -> bool {
+        self.increment_prefix(self.len())
+    }
+
+    fn output(&self, text_segments: &[String], outputs: &mut [String]) {
+        self.output_prefix(self.len(), &mut outputs[0]);
+    }
+
+    fn keyspace(&self) -> Keyspace {
+        self.prefix_keyspace(self.len())
+    }
+
+    fn seek(&mut self, index: u128, text_segments: &[String]) -> bool {
+        self.seek_prefix(index, self.len())
+    }
+
+    fn save_state(&self) -> IncrementerState {
+        self.char_idx.iter().map(|ci| *ci as u64).collect()
+    }
+
+    fn restore_state(&mut self, state: &[u64], text_segments: &[String]) -> bool {
+        self.restore_idx(state)
+    }
+
+    fn fork(&self) -> io::Result<Box<dyn RecipeIncrementer>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+// Goes through every prefix of the mask, shortest first
+// e.g. "dd" will output 0..9 and then 00..99
+#[derive(Debug, Clone)]
+pub struct MaskIncrementalIncrementer {
+    mask: MaskIncrementer,
+    min_len: usize,
+    max_len: usize,
+    cur_len: usize
+}
+
+impl MaskIncrementalIncrementer {
+    // Same checks as the validator; a max length past the end of the mask is the whole mask
+    pub fn new(mask: String, min_len: Option<usize>, max_len: Option<usize>, custom_charsets: &CustomCharsets) -> Result<Self, String> {
+        let mask = MaskIncrementer::new(mask, custom_charsets)?;
+        if min_len == Some(0) { return Err("min length must be at least 1".to_owned()); }
+        if let (Some(min), Some(max)) = (min_len, max_len) && min > max {
+            return Err(format!("min length {} is greater than max length {}", min, max));
+        }
+        if let Some(min) = min_len && min > mask.len() {
+            return Err(format!("min length {} is longer than the mask ({} characters)", min, mask.len()));
+        }
+        let max_len = max_len.unwrap_or(mask.len()).min(mask.len());
+        // An empty mask has no lengths to go through, only the single empty entry
+        let min_len = min_len.unwrap_or(1);
+        Ok(Self { mask, min_len, max_len, cur_len: min_len })
+    }
+}
+
+impl RecipeIncrementer for MaskIncrementalIncrementer {
+    fn increment(&mut self, text_segments: &[String]) -> bool {
+        // No lengths to go through, only the single empty entry
+        if self.min_len > self.max_len { return false; }
+        if self.mask.increment_prefix(self.cur_len) { return true; }
+        // Finished all combinations of this length; move on to the next length
+        self.cur_len += 1;
+        self.cur_len <= self.max_len
+    }
+
+    fn reset(&mut self, text_segments: &[String]) {
+        self.mask.reset_idx();
+        self.cur_len = self.min_len;
+    }
+
+    fn output(&self, text_segments: &[String], outputs: &mut [String]) {
+        self.mask.output_prefix(self.cur_len.min(self.max_len), &mut outputs[0]);
+    }
+
+    fn keyspace(&self) -> Keyspace {
+        // An empty mask still outputs a single empty string
+        if self.min_len > self.max_len { return Keyspace::Exact(1); }
+        (self.min_len..=self.max_len).map(|len| self.mask.prefix_keyspace(len)).sum()
+    }
+
+    fn seek(&mut self, mut index: u128, text_segments: &[String]) -> bool {
+        self.reset(text_segments);
+        // Find which length the index falls in, then the position within that length
+        for len in self.min_len..=self.max_len {
+            match self.mask.prefix_keyspace(len) {
+                Keyspace::Exact(n) if index >= n => index -= n,
+                _ => {
+                    self.cur_len = len;
+                    return self.mask.seek_prefix(index, len);
+                }
+            }
+        }
+        index == 0 && self.min_len > self.max_len
+    }
+
+    // Current length followed by the character indices
+    fn save_state(&self) -> IncrementerState {
+        let mut state = vec![self.cur_len as u64];
+        state.extend(self.mask.save_state());
+        state
+    }
+
+    fn restore_state(&mut self, state: &[u64], text_segments: &[String]) -> bool {
+        let Some((cur_len, char_idx)) = state.split_first() else { return false };
+        self.cur_len = *cur_len as usize;
+        (self.min_len..=self.max_len.max(self.min_len)).contains(&self.cur_len) && self.mask.restore_idx(char_idx)
+    }
+
+    fn fork(&self) -> io::Result<Box<dyn RecipeIncrementer>> {
+        Ok(Box::new(self.clone()))
     }
 }
@@ -8,3 +8,4 @@ pub mod mask;
 pub mod rearrange;
 pub mod replace;
 pub mod concat;
+pub mod rules;
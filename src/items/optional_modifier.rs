@@ -1,11 +1,11 @@
 use std::cell::RefCell;
 use std::rc::Rc;
-use flexstr::{local_str, LocalStr};
-use smallvec::{smallvec, SmallVec};
-use crate::candidate_generator::SV_SIZE;
+use std::io;
 use crate::items::incrementer_trait::RecipeIncrementer;
+use crate::keyspace::Keyspace;
+use crate::checkpoint::IncrementerState;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 // Iteration order: include=true, include=false
 pub struct OptionalModifierIncrementer {
     source_seg_idx: usize,
@@ -19,7 +19,7 @@ impl OptionalModifierIncrementer {
 }
 
 impl RecipeIncrementer for OptionalModifierIncrementer {
-    fn increment(&mut self, text_segments: &Vec<LocalStr>) -> bool {
+    fn increment(&mut self, text_segments: &[String]) -> bool {
         if (self.current_include) {
             self.current_include = false;
             true
@@ -28,17 +28,38 @@ impl RecipeIncrementer for OptionalModifierIncrementer {
         }
     }
 
-    fn reset(&mut self, text_segments: &Vec<LocalStr>) {
+    fn reset(&mut self, text_segments: &[String]) {
         self.current_include = true;
     }
 
-    fn output(&self, text_segments: &Vec<LocalStr>) -> SmallVec<[LocalStr; SV_SIZE]> {
-        smallvec![
-            if (self.current_include) {
-                text_segments[self.source_seg_idx].clone()
-            } else {
-                local_str!("")
-            }
-        ]
+    fn keyspace(&self) -> Keyspace {
+        Keyspace::Exact(2)
+    }
+
+    fn seek(&mut self, index: u128, text_segments: &[String]) -> bool {
+        self.current_include = index == 0;
+        index < 2
+    }
+
+    fn save_state(&self) -> IncrementerState {
+        vec![self.current_include as u64]
+    }
+
+    fn restore_state(&mut self, state: &[u64], text_segments: &[String]) -> bool {
+        match state {
+            [include @ (0 | 1)] => { self.current_include = *include == 1; true }
+            _ => false
+        }
+    }
+
+    fn output(&self, text_segments: &[String], outputs: &mut [String]) {
+        outputs[0].clear();
+        if (self.current_include) {
+            outputs[0].push_str(&text_segments[self.source_seg_idx]);
+        }
+    }
+
+    fn fork(&self) -> io::Result<Box<dyn RecipeIncrementer>> {
+        Ok(Box::new(self.clone()))
     }
 }
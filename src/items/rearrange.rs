@@ -1,44 +1,87 @@
-use std::ops::Range;
-use flexstr::LocalStr;
-use itertools::{Itertools, Permutations};
-use smallvec::{smallvec, SmallVec};
-use crate::candidate_generator::{SegIndex, SV_SIZE};
+use std::io;
+use crate::candidate_generator::SegIndex;
 use crate::items::incrementer_trait::RecipeIncrementer;
+use crate::keyspace::Keyspace;
+use crate::checkpoint::IncrementerState;
 
-#[derive(Debug)]
+// Goes through permutations in lexicographic order, so the state is fully described by the current permutation
+#[derive(Debug, Clone)]
 pub struct RearrangeIncrementer {
     source_id_indices: Vec<SegIndex>,
-    permute_iter: Permutations<Range<usize>>,
     current_permutation: Vec<usize>
 }
 
 impl RearrangeIncrementer {
     pub fn new(source_id_indices: Vec<SegIndex>) -> Self {
         let n: usize = source_id_indices.len();
-        let mut permute_iter = (0..n).permutations(n);
-        let current_permutation = permute_iter.next().unwrap();
-        Self { source_id_indices, permute_iter, current_permutation }
+        let current_permutation = (0..n).collect();
+        Self { source_id_indices, current_permutation }
     }
 }
 
 impl RecipeIncrementer for RearrangeIncrementer {
-    fn increment(&mut self, text_segments: &Vec<LocalStr>) -> bool {
-        if let Some(p) = self.permute_iter.next() {
-            self.current_permutation = p;
-            true
-        }
-        else { false }
+    fn increment(&mut self, text_segments: &[String]) -> bool {
+        let perm = &mut self.current_permutation;
+        // Find the last position that can be increased
+        let Some(pivot) = (1..perm.len()).rev().find(|i| perm[i - 1] < perm[*i]).map(|i| i - 1) else {
+            return false;
+        };
+        let successor = (pivot + 1..perm.len()).rev().find(|i| perm[*i] > perm[pivot]).unwrap();
+        perm.swap(pivot, successor);
+        perm[pivot + 1..].reverse();
+        true
+    }
+
+    fn reset(&mut self, text_segments: &[String]) {
+        let n = self.source_id_indices.len();
+        self.current_permutation = (0..n).collect();
     }
 
-    fn reset(&mut self, text_segments: &Vec<LocalStr>) {
+    fn keyspace(&self) -> Keyspace {
+        (1..=self.source_id_indices.len()).map(|n| Keyspace::Exact(n as u128)).product()
+    }
+
+    // Decodes the index as a factorial number to get the permutation directly
+    fn seek(&mut self, mut index: u128, text_segments: &[String]) -> bool {
         let n = self.source_id_indices.len();
-        self.permute_iter = (0..n).permutations(n);
-        self.current_permutation = self.permute_iter.next().unwrap();
+        let mut available: Vec<usize> = (0..n).collect();
+        let mut digits = vec![0usize; n];
+        // digits[i] picks among the n - i elements that are still available
+        for i in (0..n).rev() {
+            let radix = (n - i) as u128;
+            digits[i] = (index % radix) as usize;
+            index /= radix;
+        }
+        if index > 0 {
+            self.reset(text_segments);
+            return false;
+        }
+        self.current_permutation = digits.iter().map(|d| available.remove(*d)).collect();
+        true
     }
 
-    fn output(&self, text_segments: &Vec<LocalStr>) -> SmallVec<[LocalStr; SV_SIZE]> {
-        self.current_permutation.iter()
-            .map(|pi| text_segments[self.source_id_indices[*pi]].clone())
-            .collect::<SmallVec<[LocalStr; SV_SIZE]>>()
+    fn save_state(&self) -> IncrementerState {
+        self.current_permutation.iter().map(|i| *i as u64).collect()
     }
-}
\ No newline at end of file
+
+    fn restore_state(&mut self, state: &[u64], text_segments: &[String]) -> bool {
+        let permutation: Vec<usize> = state.iter().map(|i| *i as usize).collect();
+        // Has to contain every index exactly once
+        let mut sorted = permutation.clone();
+        sorted.sort_unstable();
+        if !sorted.into_iter().eq(0..self.source_id_indices.len()) { return false; }
+        self.current_permutation = permutation;
+        true
+    }
+
+    fn output(&self, text_segments: &[String], outputs: &mut [String]) {
+        for (output, pi) in outputs.iter_mut().zip(&self.current_permutation) {
+            output.clear();
+            output.push_str(&text_segments[self.source_id_indices[*pi]]);
+        }
+    }
+
+    fn fork(&self) -> io::Result<Box<dyn RecipeIncrementer>> {
+        Ok(Box::new(self.clone()))
+    }
+}
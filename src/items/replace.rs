@@ -1,20 +1,21 @@
-use flexstr::{LocalStr, ToLocalStr};
-use smallvec::{smallvec, SmallVec};
-use crate::candidate_generator::{SegIndex, SV_SIZE};
+use std::io;
+use crate::candidate_generator::SegIndex;
 use crate::items::incrementer_trait::RecipeIncrementer;
+use crate::keyspace::Keyspace;
+use crate::checkpoint::IncrementerState;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ReplaceIncrementer {
     source_seg_idx: SegIndex,
-    replacements: Vec<(char, char)>,
+    replacements: Vec<(String, String)>,
     // replacement_locs: Vec<Vec<usize>>,  // replacement_locs[repl_type_idx][repl_loc_idx] = char index to replace
     repl_type_idx: usize,
     repl_loc_idx: Option<usize>,
-    repl_loc: Option<usize>
+    repl_loc: Option<usize>     // Byte offset in the source text where the replaced text starts
 }
 
 impl ReplaceIncrementer {
-    pub fn new(source_seg_idx: SegIndex, replacements: Vec<(char, char)>) -> Self {
+    pub fn new(source_seg_idx: SegIndex, replacements: Vec<(String, String)>) -> Self {
         let num_repl = replacements.len();
         Self {
             source_seg_idx,
@@ -28,7 +29,8 @@ impl ReplaceIncrementer {
 }
 
 impl RecipeIncrementer for ReplaceIncrementer {
-    fn increment(&mut self, text_segments: &Vec<LocalStr>) -> bool {
+    fn increment(&mut self, text_segments: &[String]) -> bool {
+        if self.replacements.is_empty() { return false; }
         let source_txt = &text_segments[self.source_seg_idx];
         
         let mut loc_idx = 0;
@@ -39,33 +41,26 @@ impl RecipeIncrementer for ReplaceIncrementer {
         }
         
         loop {
-            let repl_locs: Vec<(usize, char)> = source_txt.chars()
-                .enumerate()
-                .filter(|(i, c)| *c == self.replacements[self.repl_type_idx].0)
-                .collect();
-            // If no more valid replacements of this type
-            if loc_idx >= repl_locs.len() {
-                loc_idx = 0;
-                self.repl_type_idx += 1;
-                
-                if self.repl_type_idx >= self.replacements.len() {
-                    // No replacements left for this word
-                    self.repl_loc_idx = None;
-                    self.repl_loc = None;
-                    return false;
-                }
-                // Loop to next replacement
-                continue;
-            } 
-            // Found valid next replacement
-            self.repl_loc_idx = Some(loc_idx);
-            self.repl_loc = Some(repl_locs[loc_idx].0);
-            return true;
+            let from = self.replacements[self.repl_type_idx].0.as_str();
+            if let Some((loc, _)) = source_txt.match_indices(from).nth(loc_idx) {
+                // Found valid next replacement
+                self.repl_loc_idx = Some(loc_idx);
+                self.repl_loc = Some(loc);
+                return true;
+            }
+            // No more valid replacements of this type
+            loc_idx = 0;
+            self.repl_type_idx += 1;
+            if self.repl_type_idx >= self.replacements.len() {
+                // No replacements left for this word
+                self.repl_loc_idx = None;
+                self.repl_loc = None;
+                return false;
+            }
         }
-        return false;
     }
 
-    fn reset(&mut self, text_segments: &Vec<LocalStr>) {
+    fn reset(&mut self, text_segments: &[String]) {
         self.repl_type_idx = 0;
         self.repl_loc_idx = None;
         self.repl_loc = None;
@@ -94,14 +89,205 @@ impl RecipeIncrementer for ReplaceIncrementer {
     }
 
 
-    fn output(&self, text_segments: &Vec<LocalStr>) -> SmallVec<[LocalStr; SV_SIZE]> {
+    // Depends on how many replaceable characters the source text has
+    fn keyspace(&self) -> Keyspace {
+        Keyspace::Bounded { min: 1, max: None }
+    }
+
+    // Replacement type followed by the location index and location, if currently replacing
+    fn save_state(&self) -> IncrementerState {
+        let mut state = vec![self.repl_type_idx as u64];
+        if let (Some(loc_idx), Some(loc)) = (self.repl_loc_idx, self.repl_loc) {
+            state.extend([loc_idx as u64, loc as u64]);
+        }
+        state
+    }
+
+    fn restore_state(&mut self, state: &[u64], text_segments: &[String]) -> bool {
+        let (repl_type_idx, loc) = match state {
+            [repl_type_idx] => (*repl_type_idx as usize, None),
+            [repl_type_idx, loc_idx, loc] => (*repl_type_idx as usize, Some((*loc_idx as usize, *loc as usize))),
+            _ => return false
+        };
+        if repl_type_idx >= self.replacements.len().max(1) { return false; }
+        // Location has to be the start of the text being replaced
+        let source_txt = &text_segments[self.source_seg_idx];
+        let from = self.replacements.get(repl_type_idx).map_or("", |r| r.0.as_str());
+        if loc.is_some_and(|(_, l)| !source_txt.get(l..).is_some_and(|rest| rest.starts_with(from))) { return false; }
+        self.repl_type_idx = repl_type_idx;
+        self.repl_loc_idx = loc.map(|l| l.0);
+        self.repl_loc = loc.map(|l| l.1);
+        true
+    }
+
+    fn output(&self, text_segments: &[String], outputs: &mut [String]) {
+        let source_txt = &text_segments[self.source_seg_idx];
+        let output = &mut outputs[0];
+        output.clear();
         match self.repl_loc {
             Some(loc) => {
-                let mut source_txt = text_segments[self.source_seg_idx].clone().to_string();
-                source_txt.replace_range(loc..loc+1, self.replacements[self.repl_type_idx].1.to_string().as_str());
-                smallvec![source_txt.to_local_str()]
+                let (from, to) = &self.replacements[self.repl_type_idx];
+                output.push_str(&source_txt[..loc]);
+                output.push_str(to);
+                output.push_str(&source_txt[loc + from.len()..]);
             },
-            None => smallvec![text_segments[self.source_seg_idx].clone()]
+            None => output.push_str(source_txt)
+        }
+    }
+
+    fn fork(&self) -> io::Result<Box<dyn RecipeIncrementer>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+// A place in the source text where one or more replacements can be made
+#[derive(Debug, Clone)]
+struct ReplaceSite {
+    start: usize,                   // Byte offset in the source text
+    len: usize,                     // Length in bytes of the text being replaced
+    alternatives: Vec<usize>        // Indices into the replacements that match here
+}
+
+// Goes through every combination of replacement sites, and every alternative for each site
+// Combinations with fewer replacements come first, starting with the original text
+#[derive(Debug, Clone)]
+pub struct ReplaceAllIncrementer {
+    source_seg_idx: SegIndex,
+    replacements: Vec<(String, String)>,
+    max_substitutions: Option<usize>,
+    sites: Vec<ReplaceSite>,        // Found in the source text on the first increment after a reset
+    chosen_sites: Vec<usize>,       // Increasing and non-overlapping indices into sites
+    chosen_alts: Vec<usize>         // Which alternative is used for each chosen site
+}
+
+impl ReplaceAllIncrementer {
+    pub fn new(source_seg_idx: SegIndex, replacements: Vec<(String, String)>, max_substitutions: Option<usize>) -> Self {
+        Self {
+            source_seg_idx,
+            replacements,
+            max_substitutions,
+            sites: Vec::new(),
+            chosen_sites: Vec::new(),
+            chosen_alts: Vec::new()
+        }
+    }
+
+    fn find_sites(&mut self, source_txt: &str) {
+        let sites = &mut self.sites;
+        sites.clear();
+        for (repl_idx, (from, _)) in self.replacements.iter().enumerate() {
+            for (start, _) in source_txt.match_indices(from.as_str()) {
+                match sites.iter_mut().find(|s| s.start == start && s.len == from.len()) {
+                    Some(site) => site.alternatives.push(repl_idx),
+                    None => sites.push(ReplaceSite { start, len: from.len(), alternatives: vec![repl_idx] })
+                }
+            }
+        }
+        sites.sort_by_key(|s| (s.start, s.len));
+    }
+
+    // Next combination of the same size in lexicographic order
+    fn next_combination(chosen: &mut [usize], n: usize) -> bool {
+        let k = chosen.len();
+        let Some(i) = (0..k).rev().find(|i| chosen[*i] < n - k + i) else { return false };
+        chosen[i] += 1;
+        for j in i + 1..k {
+            chosen[j] = chosen[j - 1] + 1;
+        }
+        true
+    }
+
+    fn is_non_overlapping(&self, chosen: &[usize]) -> bool {
+        chosen.windows(2).all(|w| self.sites[w[0]].start + self.sites[w[0]].len <= self.sites[w[1]].start)
+    }
+}
+
+impl RecipeIncrementer for ReplaceAllIncrementer {
+    fn increment(&mut self, text_segments: &[String]) -> bool {
+        // The source text only changes when this incrementer is reset, which goes back to the original text
+        if self.chosen_sites.is_empty() {
+            self.find_sites(&text_segments[self.source_seg_idx]);
+        }
+
+        // Next alternative for the current sites
+        for i in (0..self.chosen_alts.len()).rev() {
+            self.chosen_alts[i] += 1;
+            if self.chosen_alts[i] < self.sites[self.chosen_sites[i]].alternatives.len() { return true; }
+            self.chosen_alts[i] = 0;
+        }
+
+        // Next set of sites with the same number of replacements, then with one more
+        let n = self.sites.len();
+        let max_k = self.max_substitutions.unwrap_or(n).min(n);
+        loop {
+            if !Self::next_combination(&mut self.chosen_sites, n) {
+                let k = self.chosen_sites.len() + 1;
+                if k > max_k {
+                    self.chosen_sites.clear();
+                    self.chosen_alts.clear();
+                    return false;
+                }
+                self.chosen_sites.clear();
+                self.chosen_sites.extend(0..k);
+            }
+            if self.is_non_overlapping(&self.chosen_sites) {
+                self.chosen_alts.clear();
+                self.chosen_alts.resize(self.chosen_sites.len(), 0);
+                return true;
+            }
         }
     }
+
+    fn reset(&mut self, text_segments: &[String]) {
+        self.chosen_sites.clear();
+        self.chosen_alts.clear();
+    }
+
+    // Depends on how many replaceable places the source text has
+    fn keyspace(&self) -> Keyspace {
+        Keyspace::Bounded { min: 1, max: None }
+    }
+
+    // Number of replacements, then the chosen sites and their alternatives
+    fn save_state(&self) -> IncrementerState {
+        let mut state = vec![self.chosen_sites.len() as u64];
+        state.extend(self.chosen_sites.iter().chain(&self.chosen_alts).map(|i| *i as u64));
+        state
+    }
+
+    fn restore_state(&mut self, state: &[u64], text_segments: &[String]) -> bool {
+        let Some((k, rest)) = state.split_first() else { return false };
+        let k = *k as usize;
+        if rest.len() != 2 * k { return false; }
+        self.find_sites(&text_segments[self.source_seg_idx]);
+        let chosen_sites: Vec<usize> = rest[..k].iter().map(|i| *i as usize).collect();
+        let chosen_alts: Vec<usize> = rest[k..].iter().map(|i| *i as usize).collect();
+        let valid = chosen_sites.windows(2).all(|w| w[0] < w[1])
+            && chosen_sites.iter().zip(&chosen_alts).all(|(s, a)| self.sites.get(*s).is_some_and(|site| *a < site.alternatives.len()))
+            && self.is_non_overlapping(&chosen_sites)
+            && self.max_substitutions.is_none_or(|max| k <= max);
+        if valid {
+            self.chosen_sites = chosen_sites;
+            self.chosen_alts = chosen_alts;
+        }
+        valid
+    }
+
+    fn output(&self, text_segments: &[String], outputs: &mut [String]) {
+        let source_txt = &text_segments[self.source_seg_idx];
+        let output = &mut outputs[0];
+        output.clear();
+        let mut last_end = 0;
+        for (site_idx, alt_idx) in self.chosen_sites.iter().zip(&self.chosen_alts) {
+            let site = &self.sites[*site_idx];
+            output.push_str(&source_txt[last_end..site.start]);
+            output.push_str(&self.replacements[site.alternatives[*alt_idx]].1);
+            last_end = site.start + site.len;
+        }
+        output.push_str(&source_txt[last_end..]);
+    }
+
+    fn fork(&self) -> io::Result<Box<dyn RecipeIncrementer>> {
+        Ok(Box::new(self.clone()))
+    }
 }
@@ -0,0 +1,296 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use itertools::Itertools;
+use crate::candidate_generator::SegIndex;
+use crate::items::incrementer_trait::RecipeIncrementer;
+use crate::keyspace::Keyspace;
+use crate::checkpoint::IncrementerState;
+use RuleOp::*;
+
+// A single hashcat rule function; positions use hashcat's 0-9, A-Z notation
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleOp {
+    Noop,                       // :
+    Lowercase,                  // l
+    Uppercase,                  // u
+    Capitalize,                 // c
+    InvertCapitalize,           // C
+    ToggleAll,                  // t
+    ToggleAt(usize),            // TN
+    Reverse,                    // r
+    Duplicate,                  // d
+    DuplicateN(usize),          // pN
+    Reflect,                    // f
+    RotateLeft,                 // {
+    RotateRight,                // }
+    Append(char),               // $X
+    Prepend(char),              // ^X
+    DeleteFirst,                // [
+    DeleteLast,                 // ]
+    DeleteAt(usize),            // DN
+    Extract(usize, usize),      // xNM
+    Omit(usize, usize),         // ONM
+    InsertAt(usize, char),      // iNX
+    OverwriteAt(usize, char),   // oNX
+    Truncate(usize),            // 'N
+    Substitute(char, char),     // sXY
+    Purge(char),                // @X
+    DuplicateFirst(usize),      // zN
+    DuplicateLast(usize),       // ZN
+    DuplicateAll,               // q
+    SwapFront,                  // k
+    SwapBack,                   // K
+    Swap(usize, usize),         // *NM
+    TitleCase,                  // E
+    TitleCaseAfter(char),       // eX
+    ToggleAfter(usize, char),   // 3NX
+    DuplicatePrefix(usize),     // yN
+    DuplicateSuffix(usize),     // YN
+    ShiftLeft(usize),           // LN
+    ShiftRight(usize),          // RN
+    IncrementAt(usize),         // +N
+    DecrementAt(usize),         // -N
+    CopyNext(usize),            // .N
+    CopyPrevious(usize)         // ,N
+}
+
+impl RuleOp {
+    pub fn apply(&self, word: &mut Vec<char>) {
+        let len = word.len();
+        match *self {
+            Noop => {}
+            Lowercase => word.iter_mut().for_each(|c| *c = c.to_lowercase().next().unwrap_or(*c)),
+            Uppercase => word.iter_mut().for_each(|c| *c = c.to_uppercase().next().unwrap_or(*c)),
+            Capitalize => {
+                Lowercase.apply(word);
+                if let Some(c) = word.first_mut() { *c = c.to_uppercase().next().unwrap_or(*c); }
+            }
+            InvertCapitalize => {
+                Uppercase.apply(word);
+                if let Some(c) = word.first_mut() { *c = c.to_lowercase().next().unwrap_or(*c); }
+            }
+            ToggleAll => word.iter_mut().for_each(|c| *c = toggle_case(*c)),
+            ToggleAt(n) => if let Some(c) = word.get_mut(n) { *c = toggle_case(*c) },
+            Reverse => word.reverse(),
+            Duplicate => word.extend_from_within(..),
+            DuplicateN(n) => for _ in 0..n { word.extend_from_within(..len) },
+            Reflect => word.extend((0..len).rev().map(|i| word[i]).collect::<Vec<char>>()),
+            RotateLeft => if len > 0 { word.rotate_left(1) },
+            RotateRight => if len > 0 { word.rotate_right(1) },
+            Append(c) => word.push(c),
+            Prepend(c) => word.insert(0, c),
+            DeleteFirst => if len > 0 { word.remove(0); },
+            DeleteLast => { word.pop(); }
+            DeleteAt(n) => if n < len { word.remove(n); },
+            Extract(n, m) => if n + m <= len { word.truncate(n + m); word.drain(..n); },
+            Omit(n, m) => if n + m <= len { word.drain(n..n + m); },
+            InsertAt(n, c) => if n <= len { word.insert(n, c) },
+            OverwriteAt(n, c) => if let Some(w) = word.get_mut(n) { *w = c },
+            Truncate(n) => word.truncate(n),
+            Substitute(x, y) => word.iter_mut().filter(|c| **c == x).for_each(|c| *c = y),
+            Purge(x) => word.retain(|c| *c != x),
+            DuplicateFirst(n) => if let Some(c) = word.first().copied() { word.splice(0..0, std::iter::repeat_n(c, n)); },
+            DuplicateLast(n) => if let Some(c) = word.last().copied() { word.extend(std::iter::repeat_n(c, n)) },
+            DuplicateAll => *word = word.iter().flat_map(|c| [*c, *c]).collect(),
+            SwapFront => if len >= 2 { word.swap(0, 1) },
+            SwapBack => if len >= 2 { word.swap(len - 1, len - 2) },
+            Swap(n, m) => if n < len && m < len { word.swap(n, m) },
+            TitleCase => TitleCaseAfter(' ').apply(word),
+            TitleCaseAfter(separator) => {
+                Lowercase.apply(word);
+                let mut start_of_word = true;
+                for c in word.iter_mut() {
+                    if start_of_word { *c = c.to_uppercase().next().unwrap_or(*c); }
+                    start_of_word = *c == separator;
+                }
+            }
+            ToggleAfter(n, separator) => {
+                if let Some(pos) = word.iter().positions(|c| *c == separator).nth(n)
+                    && let Some(c) = word.get_mut(pos + 1) { *c = toggle_case(*c) }
+            }
+            DuplicatePrefix(n) => if n <= len { word.splice(0..0, word[..n].to_vec()); },
+            DuplicateSuffix(n) => if n <= len { word.extend_from_within(len - n..) },
+            ShiftLeft(n) => if let Some(c) = word.get_mut(n) { *c = map_byte(*c, |b| b << 1) },
+            ShiftRight(n) => if let Some(c) = word.get_mut(n) { *c = map_byte(*c, |b| b >> 1) },
+            IncrementAt(n) => if let Some(c) = word.get_mut(n) { *c = map_byte(*c, |b| b.wrapping_add(1)) },
+            DecrementAt(n) => if let Some(c) = word.get_mut(n) { *c = map_byte(*c, |b| b.wrapping_sub(1)) },
+            CopyNext(n) => if n + 1 < len { word[n] = word[n + 1] },
+            CopyPrevious(n) => if n >= 1 && n < len { word[n] = word[n - 1] }
+        }
+    }
+}
+
+fn toggle_case(c: char) -> char {
+    if c.is_lowercase() { c.to_uppercase().next().unwrap_or(c) }
+    else if c.is_uppercase() { c.to_lowercase().next().unwrap_or(c) }
+    else { c }
+}
+
+// Hashcat works on bytes; characters up to U+00FF stand for those bytes and others are left as they are
+fn map_byte(c: char, f: impl Fn(u8) -> u8) -> char {
+    u8::try_from(c).map_or(c, |b| f(b) as char)
+}
+
+fn rule_position(c: char) -> Option<usize> {
+    match c {
+        '0'..='9' => Some(c as usize - '0' as usize),
+        'A'..='Z' => Some(c as usize - 'A' as usize + 10),
+        _ => None
+    }
+}
+
+// Parses one line of a hashcat rule file; spaces between functions are ignored
+pub fn parse_rule(rule_text: &str) -> Result<Vec<RuleOp>, String> {
+    let mut chars = rule_text.chars();
+    let mut ops = Vec::new();
+    while let Some(func) = chars.next() {
+        let mut arg_char = || chars.next().ok_or_else(|| format!("missing argument for `{}`", func));
+        let op = match func {
+            ' ' => continue,
+            ':' => Noop,
+            'l' => Lowercase,
+            'u' => Uppercase,
+            'c' => Capitalize,
+            'C' => InvertCapitalize,
+            't' => ToggleAll,
+            'r' => Reverse,
+            'd' => Duplicate,
+            'f' => Reflect,
+            '{' => RotateLeft,
+            '}' => RotateRight,
+            '[' => DeleteFirst,
+            ']' => DeleteLast,
+            'q' => DuplicateAll,
+            'k' => SwapFront,
+            'K' => SwapBack,
+            'E' => TitleCase,
+            'e' => TitleCaseAfter(arg_char()?),
+            '$' => Append(arg_char()?),
+            '^' => Prepend(arg_char()?),
+            '@' => Purge(arg_char()?),
+            's' => { let x = arg_char()?; Substitute(x, arg_char()?) }
+            'T' | 'p' | 'D' | '\'' | 'z' | 'Z' | 'y' | 'Y' | 'L' | 'R' | '+' | '-' | '.' | ',' => {
+                let n_char = arg_char()?;
+                let n = rule_position(n_char).ok_or_else(|| format!("invalid position `{}` for `{}`", n_char, func))?;
+                match func {
+                    'T' => ToggleAt(n),
+                    'p' => DuplicateN(n),
+                    'D' => DeleteAt(n),
+                    '\'' => Truncate(n),
+                    'z' => DuplicateFirst(n),
+                    'Z' => DuplicateLast(n),
+                    'y' => DuplicatePrefix(n),
+                    'Y' => DuplicateSuffix(n),
+                    'L' => ShiftLeft(n),
+                    'R' => ShiftRight(n),
+                    '+' => IncrementAt(n),
+                    '-' => DecrementAt(n),
+                    '.' => CopyNext(n),
+                    _ => CopyPrevious(n)
+                }
+            }
+            'x' | 'O' | '*' | 'i' | 'o' | '3' => {
+                let n_char = arg_char()?;
+                let n = rule_position(n_char).ok_or_else(|| format!("invalid position `{}` for `{}`", n_char, func))?;
+                let second = arg_char()?;
+                match func {
+                    'i' => InsertAt(n, second),
+                    'o' => OverwriteAt(n, second),
+                    '3' => ToggleAfter(n, second),
+                    _ => {
+                        let m = rule_position(second).ok_or_else(|| format!("invalid position `{}` for `{}`", second, func))?;
+                        match func {
+                            'x' => Extract(n, m),
+                            'O' => Omit(n, m),
+                            _ => Swap(n, m)
+                        }
+                    }
+                }
+            }
+            '<' | '>' | '_' | '!' | '/' | '(' | ')' | '=' | '%' | 'Q' =>
+                return Err(format!("rejection rule `{}` is not supported", func)),
+            'M' | '4' | '6' | 'X' => return Err(format!("memory rule `{}` is not supported", func)),
+            _ => return Err(format!("unsupported rule function `{}`", func))
+        };
+        ops.push(op);
+    }
+    Ok(ops)
+}
+
+// Applies one rule from a hashcat rule file per iteration
+#[derive(Debug, Clone)]
+pub struct RulesIncrementer {
+    source_seg_idx: SegIndex,
+    rules: Arc<Vec<Vec<RuleOp>>>,    // Shared with the copies for other threads
+    rule_idx: usize,
+    word: RefCell<Vec<char>>    // Reused between outputs so applying a rule doesn't allocate
+}
+
+impl RulesIncrementer {
+    // Like hashcat, rules that can't be parsed are skipped; a warning for each is returned for the caller to show
+    pub fn new(source_seg_idx: SegIndex, filename: String) -> Result<(Self, Vec<String>), String> {
+        let rules_text = fs::read_to_string(&filename).map_err(|e| format!("can't read {}: {}", filename, e))?;
+        let mut warnings = Vec::new();
+        let rules: Vec<Vec<RuleOp>> = rules_text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .filter_map(|(i, line)| parse_rule(line)
+                .inspect_err(|e| warnings.push(format!("skipping rule on line {} of {}: {}", i + 1, filename, e)))
+                .ok())
+            .collect();
+        Ok((Self { source_seg_idx, rules: Arc::new(rules), rule_idx: 0, word: RefCell::new(Vec::new()) }, warnings))
+    }
+}
+
+impl RecipeIncrementer for RulesIncrementer {
+    fn increment(&mut self, text_segments: &[String]) -> bool {
+        self.rule_idx += 1;
+        self.rule_idx < self.rules.len()
+    }
+
+    fn reset(&mut self, text_segments: &[String]) {
+        self.rule_idx = 0;
+    }
+
+    fn keyspace(&self) -> Keyspace {
+        // Without any rules the source text is output once unchanged
+        Keyspace::Exact(self.rules.len().max(1) as u128)
+    }
+
+    fn seek(&mut self, index: u128, text_segments: &[String]) -> bool {
+        if index >= self.keyspace().min() { return false; }
+        self.rule_idx = index as usize;
+        true
+    }
+
+    fn save_state(&self) -> IncrementerState {
+        vec![self.rule_idx as u64]
+    }
+
+    fn restore_state(&mut self, state: &[u64], text_segments: &[String]) -> bool {
+        match state {
+            [rule_idx] if (*rule_idx as u128) < self.keyspace().min() => { self.rule_idx = *rule_idx as usize; true }
+            _ => false
+        }
+    }
+
+    fn output(&self, text_segments: &[String], outputs: &mut [String]) {
+        let source_txt = &text_segments[self.source_seg_idx];
+        let output = &mut outputs[0];
+        output.clear();
+        let Some(rule) = self.rules.get(self.rule_idx) else { return output.push_str(source_txt) };
+        let mut word = self.word.borrow_mut();
+        word.clear();
+        word.extend(source_txt.chars());
+        for op in rule {
+            op.apply(&mut word);
+        }
+        output.extend(word.iter());
+    }
+
+    fn fork(&self) -> io::Result<Box<dyn RecipeIncrementer>> {
+        Ok(Box::new(self.clone()))
+    }
+}
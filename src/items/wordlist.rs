@@ -1,44 +1,499 @@
-use std::io::{BufRead, BufReader, Seek};
-use std::fs::File;
-use flexstr::{local_str, LocalStr, ToLocalStr};
-use smallvec::{smallvec, SmallVec};
-use trim_in_place::TrimInPlace;
-use crate::candidate_generator::SV_SIZE;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::fs::{self, File};
+use std::ops::Deref;
+use std::sync::Arc;
+use memmap2::Mmap;
+use regex::Regex;
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use xz2::read::XzDecoder;
+use crate::items::mask::{CustomCharsets, MaskCharType};
+use crate::recipe_step::WordlistOptions;
 use super::incrementer_trait::RecipeIncrementer;
+use crate::keyspace::Keyspace;
+use crate::checkpoint::IncrementerState;
+
+// How wordlist files are read; auto picks memory when the files of a step are uncompressed and add up to at most AUTO_MEMORY_MAX_SIZE
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum WordlistMode {
+    #[default]
+    Auto,
+    Memory,     // Load (or mmap) the whole file once and index its lines, so resets are free
+    Stream      // Read line by line, reopening the file on every reset
+}
+
+// Options of the wordlist step, with the charset expanded
+#[derive(Debug, Clone, Default)]
+pub struct WordFilter {
+    min_len: Option<usize>,
+    max_len: Option<usize>,
+    allowed_chars: Option<HashSet<char>>,
+    match_regex: Option<Regex>,
+    exclude_regex: Option<Regex>,
+    unique: bool
+}
+
+impl WordFilter {
+    pub fn new(options: WordlistOptions, custom_charsets: &CustomCharsets) -> Result<WordFilter, String> {
+        let allowed_chars = options.charset
+            .map(|definition| MaskCharType::expand_charset(&definition, custom_charsets).map(|charset| charset.chars().collect()))
+            .transpose()?;
+        Ok(WordFilter {
+            min_len: options.min_len,
+            max_len: options.max_len,
+            allowed_chars,
+            match_regex: options.match_regex,
+            exclude_regex: options.exclude_regex,
+            unique: options.unique
+        })
+    }
+
+    fn keeps_everything(&self) -> bool {
+        self.min_len.is_none() && self.max_len.is_none() && self.allowed_chars.is_none()
+            && self.match_regex.is_none() && self.exclude_regex.is_none() && !self.unique
+    }
+
+    // Everything except uniqueness, which depends on the words before
+    fn accepts(&self, word: &str) -> bool {
+        if self.min_len.is_some() || self.max_len.is_some() {
+            let len = word.chars().count();
+            if self.min_len.is_some_and(|min| len < min) || self.max_len.is_some_and(|max| len > max) { return false; }
+        }
+        self.allowed_chars.as_ref().is_none_or(|allowed| word.chars().all(|c| allowed.contains(&c)))
+            && self.match_regex.as_ref().is_none_or(|regex| regex.is_match(word))
+            && self.exclude_regex.as_ref().is_none_or(|regex| !regex.is_match(word))
+    }
+}
+
+const AUTO_MEMORY_MAX_SIZE: u64 = 256 * 1024 * 1024;
+
+impl WordlistMode {
+    fn use_memory(&self, filenames: &[String]) -> bool {
+        match self {
+            WordlistMode::Auto => filenames.iter()
+                .map(|filename| {
+                    let mut file = File::open(filename)?;
+                    // Compressed files can be many times larger once decompressed, so they are always streamed
+                    if detect_compression(&mut file)? != Compression::None { return Ok(u64::MAX); }
+                    Ok(file.metadata()?.len())
+                })
+                .try_fold(0u64, |total, size: io::Result<u64>| size.map(|size| total.saturating_add(size)))
+                .is_ok_and(|size| size <= AUTO_MEMORY_MAX_SIZE),
+            WordlistMode::Memory => true,
+            WordlistMode::Stream => false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Compression {
+    None,
+    Gzip,
+    Xz,
+    Zstd,
+    Bzip2
+}
+
+impl Compression {
+    fn detect(magic: &[u8]) -> Compression {
+        if magic.starts_with(&[0x1f, 0x8b]) { Compression::Gzip }
+        else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) { Compression::Xz }
+        else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) { Compression::Zstd }
+        else if magic.starts_with(b"BZh") { Compression::Bzip2 }
+        else { Compression::None }
+    }
+}
+
+fn detect_compression(file: &mut File) -> io::Result<Compression> {
+    let mut magic = Vec::with_capacity(6);
+    file.take(6).read_to_end(&mut magic)?;
+    file.rewind()?;
+    Ok(Compression::detect(&magic))
+}
+
+// Opens a wordlist at a byte offset of its (decompressed) text
+// Compressed streams can't seek, so they are decompressed from the start and skipped ahead
+fn open_wordlist(filename: &str, offset: u64) -> io::Result<BufReader<Box<dyn Read + Send>>> {
+    let mut file = File::open(filename)?;
+    let decoder: Box<dyn Read + Send> = match detect_compression(&mut file)? {
+        Compression::None => {
+            file.seek(SeekFrom::Start(offset))?;
+            return Ok(BufReader::new(Box::new(file)));
+        }
+        Compression::Gzip => Box::new(MultiGzDecoder::new(file)),
+        Compression::Xz => Box::new(XzDecoder::new_multi_decoder(file)),
+        Compression::Zstd => Box::new(zstd::Decoder::new(file)?),
+        Compression::Bzip2 => Box::new(MultiBzDecoder::new(file))
+    };
+    let mut reader = BufReader::new(decoder);
+    io::copy(&mut (&mut reader).take(offset), &mut io::sink())?;
+    Ok(reader)
+}
+
+// Text of a wordlist in memory; plain files are memory-mapped and compressed ones are decompressed
+enum WordlistText {
+    Loaded(Vec<u8>),
+    Mapped(Mmap)
+}
+
+impl Deref for WordlistText {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match self {
+            WordlistText::Loaded(bytes) => bytes,
+            WordlistText::Mapped(mmap) => mmap
+        }
+    }
+}
+
+impl WordlistText {
+    fn load(filename: &str) -> io::Result<WordlistText> {
+        let mut file = File::open(filename)?;
+        if detect_compression(&mut file)? != Compression::None {
+            let mut bytes = Vec::new();
+            open_wordlist(filename, 0)?.read_to_end(&mut bytes)?;
+            return Ok(WordlistText::Loaded(bytes));
+        }
+        // Mapping an empty file fails on some platforms
+        if file.metadata()?.len() == 0 {
+            return Ok(WordlistText::Loaded(Vec::new()));
+        }
+        // The wordlist is not expected to change while candidates are generated
+        unsafe { Mmap::map(&file) }.map(WordlistText::Mapped)
+    }
+}
+
+// A wordlist file loaded into memory
+struct IndexedWordlist {
+    text: WordlistText,
+    line_starts: Vec<usize>     // Byte offset of each line that passes the filter
+}
+
+// File index and byte offset of a line, which stays valid when the wordlist is opened again
+type WordlistPosition = (usize, u64);
+
+// Reads the files of a wordlist step one after another as a single list
+enum WordlistReader {
+    Stream {
+        filenames: Vec<String>,
+        file_idx: usize,
+        reader: BufReader<Box<dyn Read + Send>>,
+        next_line_start: u64,
+        line: Vec<u8>,
+        filter: Arc<WordFilter>,
+        seen: HashSet<String>       // Only used with the unique option
+    },
+    Memory {
+        files: Arc<Vec<IndexedWordlist>>,      // Shared with the copies for other threads
+        file_idx: usize,
+        next_line: usize
+    }
+}
+
+impl WordlistReader {
+    fn open(filenames: Vec<String>, mode: WordlistMode, filter: WordFilter) -> io::Result<WordlistReader> {
+        if !mode.use_memory(&filenames) {
+            return Self::open_stream(filenames, Arc::new(filter));
+        }
+        // Unique words are tracked across all files, since they are one list
+        let mut seen = HashSet::new();
+        let mut files = Vec::with_capacity(filenames.len());
+        for filename in &filenames {
+            let text = WordlistText::load(filename)?;
+            // Like read_line, a newline at the very end doesn't start another line
+            let all_line_starts = (!text.is_empty()).then_some(0)
+                .into_iter()
+                .chain(text.iter().enumerate().filter(|(_, b)| **b == b'\n').map(|(i, _)| i + 1).filter(|start| *start < text.len()));
+            // Filtering once here means repeating the wordlist doesn't filter it again
+            let line_starts = if filter.keeps_everything() {
+                all_line_starts.collect()
+            } else {
+                all_line_starts
+                    .filter(|start| {
+                        let word = Self::line_at(&text, *start);
+                        filter.accepts(&word) && (!filter.unique || seen.insert(word.into_owned()))
+                    })
+                    .collect()
+            };
+            files.push(IndexedWordlist { text, line_starts });
+        }
+        Ok(WordlistReader::Memory { files: Arc::new(files), file_idx: 0, next_line: 0 })
+    }
+
+    fn open_stream(filenames: Vec<String>, filter: Arc<WordFilter>) -> io::Result<WordlistReader> {
+        Ok(WordlistReader::Stream {
+            reader: open_wordlist(&filenames[0], 0)?,
+            filenames,
+            file_idx: 0,
+            next_line_start: 0,
+            line: Vec::new(),
+            filter,
+            seen: HashSet::new()
+        })
+    }
+
+    // Reader at the same position for another thread; loaded files are shared, streams are opened again
+    fn fork(&self) -> io::Result<WordlistReader> {
+        match self {
+            WordlistReader::Stream { filenames, file_idx, next_line_start, filter, seen, .. } => Ok(WordlistReader::Stream {
+                filenames: filenames.clone(),
+                file_idx: *file_idx,
+                reader: open_wordlist(&filenames[*file_idx], *next_line_start)?,
+                next_line_start: *next_line_start,
+                line: Vec::new(),
+                filter: filter.clone(),
+                seen: seen.clone()
+            }),
+            WordlistReader::Memory { files, file_idx, next_line } =>
+                Ok(WordlistReader::Memory { files: files.clone(), file_idx: *file_idx, next_line: *next_line })
+        }
+    }
+
+    fn line_at(text: &[u8], start: usize) -> Cow<'_, str> {
+        let end = text[start..].iter().position(|b| *b == b'\n').map_or(text.len(), |len| start + len);
+        match String::from_utf8_lossy(&text[start..end]) {
+            Cow::Borrowed(line) => Cow::Borrowed(line.trim()),
+            Cow::Owned(line) => Cow::Owned(line.trim().to_owned())
+        }
+    }
+
+    // Reads the trimmed text of the next line that passes the filter into `word` and returns its position,
+    // moving on to the next file at the end of one
+    // Invalid UTF-8 is replaced the same way in both modes, so they give the same words
+    fn next_line(&mut self, word: &mut String) -> io::Result<Option<WordlistPosition>> {
+        match self {
+            WordlistReader::Stream { filenames, file_idx, reader, next_line_start, line, filter, seen } => loop {
+                line.clear();
+                let len = reader.read_until(b'\n', line)?;
+                if len == 0 {
+                    if *file_idx + 1 >= filenames.len() { return Ok(None); }
+                    *file_idx += 1;
+                    *reader = open_wordlist(&filenames[*file_idx], 0)?;
+                    *next_line_start = 0;
+                    continue;
+                }
+                let line_start = *next_line_start;
+                *next_line_start += len as u64;
+                let text = String::from_utf8_lossy(line);
+                let trimmed = text.trim();
+                if !filter.accepts(trimmed) { continue; }
+                if filter.unique && !seen.insert(trimmed.to_owned()) { continue; }
+                word.clear();
+                word.push_str(trimmed);
+                return Ok(Some((*file_idx, line_start)));
+            }
+            WordlistReader::Memory { files, file_idx, next_line } => loop {
+                let file = &files[*file_idx];
+                if let Some(start) = file.line_starts.get(*next_line) {
+                    *next_line += 1;
+                    word.clear();
+                    word.push_str(&Self::line_at(&file.text, *start));
+                    return Ok(Some((*file_idx, *start as u64)));
+                }
+                if *file_idx + 1 >= files.len() { return Ok(None); }
+                *file_idx += 1;
+                *next_line = 0;
+            }
+        }
+    }
+
+    // Moves to the line at this position
+    fn seek(&mut self, position: WordlistPosition) -> io::Result<bool> {
+        let (target_file, offset) = position;
+        match self {
+            WordlistReader::Stream { filenames, file_idx, reader, next_line_start, filter, seen, .. } => {
+                if target_file >= filenames.len() { return Ok(false); }
+                seen.clear();
+                if filter.unique && position > (0, 0) {
+                    // Read up to the position again to know which words were already output
+                    let mut replay = Self::open_stream(filenames.clone(), filter.clone())?;
+                    let mut word = String::new();
+                    while let Some(line_position) = replay.next_line(&mut word)? {
+                        if line_position < position { continue; }
+                        if let WordlistReader::Stream { seen: replay_seen, .. } = &mut replay {
+                            replay_seen.remove(&word);
+                            *seen = std::mem::take(replay_seen);
+                        }
+                        break;
+                    }
+                }
+                // Reopening works for compressed wordlists too
+                *reader = open_wordlist(&filenames[target_file], offset)?;
+                *file_idx = target_file;
+                *next_line_start = offset;
+                Ok(true)
+            }
+            WordlistReader::Memory { files, file_idx, next_line } => {
+                let Some(file) = files.get(target_file) else { return Ok(false) };
+                *file_idx = target_file;
+                match file.line_starts.binary_search(&(offset as usize)) {
+                    Ok(line) => { *next_line = line; Ok(true) }
+                    Err(_) => { *next_line = 0; Ok(offset == 0) }
+                }
+            }
+        }
+    }
+
+    // Moves to the line with this index in the whole list, if lines can be accessed directly
+    fn seek_line(&mut self, index: u128) -> Option<bool> {
+        let WordlistReader::Memory { files, file_idx, next_line } = self else { return None };
+        let mut remaining = index;
+        for (i, file) in files.iter().enumerate() {
+            let lines = file.line_starts.len() as u128;
+            if remaining < lines {
+                *file_idx = i;
+                *next_line = remaining as usize;
+                return Some(true);
+            }
+            remaining -= lines;
+        }
+        Some(false)
+    }
+
+    fn count_lines(&self) -> io::Result<usize> {
+        match self {
+            // Count lines with a separate reader so the current position is kept
+            WordlistReader::Stream { filenames, filter, .. } => {
+                let mut counter = Self::open_stream(filenames.clone(), filter.clone())?;
+                let mut word = String::new();
+                let mut lines = 0;
+                while counter.next_line(&mut word)?.is_some() { lines += 1; }
+                Ok(lines)
+            }
+            WordlistReader::Memory { files, .. } => Ok(files.iter().map(|file| file.line_starts.len()).sum())
+        }
+    }
+}
 
-#[derive(Debug)]
 pub struct WordlistIncrementer {
-    reader: BufReader<File>,
-    current_value: LocalStr,
-    current_val_string: String
+    reader: WordlistReader,
+    position: WordlistPosition,     // Of the current line, used for checkpoints
+    line_idx: Option<u128>,         // Index of the current line in the list, unknown after restoring a stream
+    current_value: String,
+    description: String,            // The files, for errors
+    error: Option<io::Error>        // A read error that ended the list early
 }
 
 impl WordlistIncrementer {
-    pub fn new(filename: String) -> WordlistIncrementer {
+    pub fn new(filenames: Vec<String>, mode: WordlistMode, filter: WordFilter) -> Result<WordlistIncrementer, String> {
+        let description = filenames.join(", ");
+        let reader = WordlistReader::open(filenames, mode, filter).map_err(|e| format!("can't read {}: {}", description, e))?;
         let mut item = WordlistIncrementer {
-            reader: BufReader::new(File::open(&filename).unwrap_or_else(|_| panic!("Error reading file {}", &filename))),
-            current_value: local_str!(""),
-            current_val_string: String::new()
+            reader,
+            position: (0, 0),
+            line_idx: Some(0),
+            current_value: String::new(),
+            description,
+            error: None
         };
-        item.increment(&vec![]);
-        item
+        item.read_next();
+        Ok(item)
+    }
+
+    fn read_next(&mut self) -> bool {
+        match self.reader.next_line(&mut self.current_value) {
+            Ok(Some(position)) => {
+                self.position = position;
+                true
+            }
+            Ok(None) => {
+                self.current_value.clear();
+                false
+            }
+            Err(e) => {
+                self.fail(e);
+                false
+            }
+        }
+    }
+
+    fn fail(&mut self, error: io::Error) {
+        self.current_value.clear();
+        self.error.get_or_insert_with(|| io::Error::new(error.kind(), format!("can't read wordlist {}: {}", self.description, error)));
     }
 }
 
 impl RecipeIncrementer for WordlistIncrementer {
-    fn increment(&mut self, text_segments: &Vec<LocalStr>) -> bool {
-        self.current_val_string.clear();
-        let result = self.reader.read_line(&mut self.current_val_string);
-        self.current_val_string.trim_in_place();
-        self.current_value = self.current_val_string.to_local_str();
-        result.is_ok_and(|n| n > 0)
+    fn increment(&mut self, text_segments: &[String]) -> bool {
+        let success = self.read_next();
+        if success { self.line_idx = self.line_idx.map(|i| i + 1); }
+        success
+    }
+
+    fn reset(&mut self, text_segments: &[String]) {
+        if let Err(e) = self.reader.seek((0, 0)) {
+            self.fail(e);
+            return;
+        }
+        self.position = (0, 0);
+        self.line_idx = Some(0);
+        self.read_next();
+    }
+
+    fn seek(&mut self, index: u128, text_segments: &[String]) -> bool {
+        match self.reader.seek_line(index) {
+            Some(true) => {
+                self.line_idx = Some(index);
+                self.read_next()
+            }
+            // An empty list still has its single empty word
+            Some(false) => {
+                if index > 0 { return false; }
+                self.reset(text_segments);
+                true
+            }
+            None => {
+                // Streams can only go forward, so start over when going back
+                if self.line_idx.is_none_or(|current| current > index) { self.reset(text_segments); }
+                while self.line_idx < Some(index) {
+                    if !self.increment(text_segments) { return false; }
+                }
+                true
+            }
+        }
+    }
+
+    fn keyspace(&self) -> Keyspace {
+        match self.reader.count_lines() {
+            // An empty file still outputs a single empty word
+            Ok(lines) => Keyspace::Exact(lines.max(1) as u128),
+            Err(_) => Keyspace::Bounded { min: 1, max: None }
+        }
     }
 
-    fn reset(&mut self, text_segments: &Vec<LocalStr>) {
-        self.reader.rewind().unwrap();
-        self.increment(&vec![]);
+    fn save_state(&self) -> IncrementerState {
+        vec![self.position.0 as u64, self.position.1]
     }
-    fn output(&self, text_segments: &Vec<LocalStr>) -> SmallVec<[LocalStr; SV_SIZE]> {
-        smallvec![self.current_value.clone()]
+
+    fn restore_state(&mut self, state: &[u64], text_segments: &[String]) -> bool {
+        let [file_idx, line_start] = state else { return false };
+        let position = (*file_idx as usize, *line_start);
+        if !self.reader.seek(position).unwrap_or(false) { return false; }
+        self.position = position;
+        self.line_idx = None;
+        // An empty list has no line to read but still has a valid state
+        self.read_next() || position == (0, 0)
     }
-}
\ No newline at end of file
+
+    fn output(&self, text_segments: &[String], outputs: &mut [String]) {
+        outputs[0].clear();
+        outputs[0].push_str(&self.current_value);
+    }
+
+    fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    fn fork(&self) -> io::Result<Box<dyn RecipeIncrementer>> {
+        Ok(Box::new(WordlistIncrementer {
+            reader: self.reader.fork()?,
+            position: self.position,
+            line_idx: self.line_idx,
+            current_value: self.current_value.clone(),
+            description: self.description.clone(),
+            error: self.error.as_ref().map(|e| io::Error::new(e.kind(), e.to_string()))
+        }))
+    }
+}
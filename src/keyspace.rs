@@ -0,0 +1,80 @@
+use std::fmt::{Display, Formatter};
+use std::iter::{Product, Sum};
+use std::ops::{Add, Mul};
+
+// Number of candidates an incrementer (or a whole recipe) will produce
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Keyspace {
+    Exact(u128),
+    // Depends on the generated text, or is too large to count; max of None means no known upper bound
+    Bounded { min: u128, max: Option<u128> }
+}
+
+impl Keyspace {
+    pub fn min(&self) -> u128 {
+        match self {
+            Keyspace::Exact(n) => *n,
+            Keyspace::Bounded { min, .. } => *min
+        }
+    }
+
+    pub fn max(&self) -> Option<u128> {
+        match self {
+            Keyspace::Exact(n) => Some(*n),
+            Keyspace::Bounded { max, .. } => *max
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Keyspace::Exact(_))
+    }
+
+    // Overflowing an exact count turns it into a lower bound
+    fn combine(self, other: Keyspace, op: fn(u128, u128) -> Option<u128>) -> Keyspace {
+        if let (Keyspace::Exact(a), Keyspace::Exact(b)) = (self, other) && let Some(n) = op(a, b) {
+            return Keyspace::Exact(n);
+        }
+        let min = op(self.min(), other.min()).unwrap_or(u128::MAX);
+        let max = match (self.max(), other.max()) {
+            (Some(a), Some(b)) => op(a, b),
+            _ => None
+        };
+        Keyspace::Bounded { min, max }
+    }
+}
+
+impl Mul for Keyspace {
+    type Output = Keyspace;
+    fn mul(self, rhs: Keyspace) -> Keyspace {
+        self.combine(rhs, u128::checked_mul)
+    }
+}
+
+impl Add for Keyspace {
+    type Output = Keyspace;
+    fn add(self, rhs: Keyspace) -> Keyspace {
+        self.combine(rhs, u128::checked_add)
+    }
+}
+
+impl Product for Keyspace {
+    fn product<I: Iterator<Item = Keyspace>>(iter: I) -> Keyspace {
+        iter.fold(Keyspace::Exact(1), Mul::mul)
+    }
+}
+
+impl Sum for Keyspace {
+    fn sum<I: Iterator<Item = Keyspace>>(iter: I) -> Keyspace {
+        iter.fold(Keyspace::Exact(0), Add::add)
+    }
+}
+
+impl Display for Keyspace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Keyspace::Exact(n) => write!(f, "{}", n),
+            Keyspace::Bounded { min, max: Some(max) } => write!(f, "between {} and {}", min, max),
+            Keyspace::Bounded { min, max: None } => write!(f, "at least {}", min)
+        }
+    }
+}
@@ -0,0 +1,65 @@
+#![allow(unused)]
+
+pub mod recipe_parser;
+pub mod recipe_step;
+pub mod candidate_generator;
+pub mod recipe_validator;
+pub mod keyspace;
+pub mod checkpoint;
+pub mod parallel_generator;
+pub mod custom_step;
+
+pub mod items;
+
+use std::fmt::{Display, Formatter};
+use itertools::Itertools;
+pub use crate::candidate_generator::{CandidateGenerator, GeneratorOptions};
+pub use crate::custom_step::{CustomStepContext, StepRegistry};
+pub use crate::items::incrementer_trait::RecipeIncrementer;
+pub use crate::keyspace::Keyspace;
+pub use crate::recipe_parser::RecipeParseError;
+pub use crate::recipe_step::Recipe;
+pub use crate::recipe_validator::RecipeDiagnostic;
+use crate::recipe_parser::RecipeParser;
+use crate::recipe_validator::RecipeValidator;
+
+// Why a recipe can't be used
+#[derive(Debug, Clone)]
+pub enum RecipeError {
+    Parse(Vec<RecipeParseError>),
+    Invalid(Vec<RecipeDiagnostic>),
+    Load(String)                        // A file used by the recipe can't be read
+}
+
+impl Display for RecipeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecipeError::Parse(errors) => write!(f, "{}", errors.iter().map(|e| format!("error: {}", e)).join("\n")),
+            RecipeError::Invalid(diagnostics) => write!(f, "{}", diagnostics.iter().join("\n")),
+            RecipeError::Load(error) => write!(f, "error: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for RecipeError {}
+
+// The options are needed for custom steps, which are parsed by their own argument parsers
+pub fn parse_recipe(recipe_text: &str, options: &GeneratorOptions) -> Result<Recipe, RecipeError> {
+    RecipeParser::parse_with_options(recipe_text.to_owned(), options).map_err(RecipeError::Parse)
+}
+
+// Checks the recipe first, since the generator doesn't check everything the validator does
+// Warnings, e.g. for skipped rules, are kept in the generator
+pub fn build_generator(recipe: Recipe, options: GeneratorOptions) -> Result<CandidateGenerator, RecipeError> {
+    let diagnostics = RecipeValidator::validate(&recipe, &options);
+    if !diagnostics.is_empty() {
+        return Err(RecipeError::Invalid(diagnostics));
+    }
+    CandidateGenerator::from_recipe(recipe, options).map_err(RecipeError::Load)
+}
+
+// Generator for a recipe's text; it is an Iterator over the candidates, or next_candidate can be used to avoid
+// allocating a String for each one
+pub fn generator_from_str(recipe_text: &str, options: GeneratorOptions) -> Result<CandidateGenerator, RecipeError> {
+    build_generator(parse_recipe(recipe_text, &options)?, options)
+}
@@ -1,15 +1,18 @@
 #![allow(unused)]
 
-mod recipe_parser;
-mod recipe_step;
-mod candidate_generator;
-
-mod items;
-
-use std::io::BufWriter;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use clap::Parser;
-use crate::candidate_generator::CandidateGenerator;
-use crate::recipe_parser::RecipeParser;
+use password_chef::candidate_generator::{CandidateGenerator, GeneratorOptions};
+use password_chef::items::mask::MaskCharType;
+use password_chef::items::wordlist::WordlistMode;
+use password_chef::checkpoint::Checkpoint;
+use password_chef::parallel_generator::{self, UNITS_PER_THREAD};
+use password_chef::recipe_parser::RecipeParser;
+use password_chef::recipe_validator::RecipeValidator;
 
 #[derive(Parser, Debug)]
 #[command(version, about = "Password candidate generator using step-by-step recipes")]
@@ -18,10 +21,64 @@ struct PasswordChefArgs
     #[arg(short, long, value_name="FILE")]
     recipe: String,
 
-    #[arg(short='w', long, help="Directory where wordlists will be checked", value_name="DIR")]
-    wordlist_dir: Option<String>
+    #[arg(short='w', long, help="Directory where wordlists will be checked, after the recipe's directory (can be repeated)",
+          value_name="DIR", env="PASSWORDCHEF_WORDLIST_DIR", value_delimiter=PATH_LIST_SEPARATOR)]
+    wordlist_dir: Vec<PathBuf>,
+
+    #[arg(long, help="How wordlists are read: memory loads each one once so it can be repeated quickly, stream reads it line by line \
+          (auto uses memory for uncompressed files up to 256 MiB)", value_name="MODE", value_enum, default_value_t=WordlistMode::Auto)]
+    wordlist_mode: WordlistMode,
+
+    #[arg(short='1', long, help="Custom charset for ?1, e.g. ?l?d! (can be replaced by a charset step in the recipe)", value_name="CHARSET")]
+    custom_charset1: Option<String>,
+
+    #[arg(short='2', long, help="Custom charset for ?2", value_name="CHARSET")]
+    custom_charset2: Option<String>,
+
+    #[arg(short='3', long, help="Custom charset for ?3", value_name="CHARSET")]
+    custom_charset3: Option<String>,
+
+    #[arg(short='4', long, help="Custom charset for ?4", value_name="CHARSET")]
+    custom_charset4: Option<String>,
+
+    #[arg(long, help="Check the recipe for errors without generating candidates")]
+    check: bool,
+
+    #[arg(long, help="Print the number of candidates the recipe will generate, without generating them")]
+    count: bool,
+
+    #[arg(long, help="Skip this many candidates before printing", value_name="N", default_value_t=0)]
+    skip: u128,
+
+    #[arg(long, help="Stop after printing this many candidates", value_name="N")]
+    limit: Option<u128>,
+
+    #[arg(long, help="Periodically save progress to this file, and also when interrupted with Ctrl+C", value_name="FILE")]
+    checkpoint: Option<String>,
+
+    #[arg(long, help="Seconds between checkpoints", value_name="SECS", default_value_t=60)]
+    checkpoint_interval: u64,
+
+    #[arg(long, help="Continue from a checkpoint file, saving further checkpoints to it unless --checkpoint is given",
+          value_name="FILE", conflicts_with="skip")]
+    restore: Option<String>,
+
+    #[arg(short='t', long, help="Number of threads generating candidates, 0 for one per CPU core", value_name="N", default_value_t=1,
+          conflicts_with_all=["skip", "limit", "checkpoint", "restore"])]
+    threads: usize,
+
+    #[arg(long, help="With --threads, print candidates in whatever order the threads finish them, which is faster", requires="threads")]
+    unordered: bool
 }
 
+#[cfg(windows)]
+const PATH_LIST_SEPARATOR: char = ';';
+#[cfg(not(windows))]
+const PATH_LIST_SEPARATOR: char = ':';
+
+// How many candidates to print between checking whether a checkpoint should be written
+const CHECKPOINT_CHECK_PERIOD: u64 = 4096;
+
 fn main() -> std::io::Result<()> {
     let args = PasswordChefArgs::parse();
 
@@ -29,11 +86,153 @@ fn main() -> std::io::Result<()> {
 
     let recipe_text = std::fs::read_to_string(&args.recipe)?;
 
-    let recipe = RecipeParser::parse(recipe_text);
+    let recipe = match RecipeParser::parse(recipe_text) {
+        Ok(recipe) => recipe,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("{}", error.report(&args.recipe));
+            }
+            eprintln!("error: aborting due to {} previous error{}", errors.len(), if errors.len() == 1 { "" } else { "s" });
+            std::process::exit(1);
+        }
+    };
+
+    let mut options = GeneratorOptions::default();
+    // Relative paths are looked for next to the recipe first
+    let recipe_dir = Path::new(&args.recipe).parent().unwrap_or(Path::new("")).to_path_buf();
+    options.search_dirs = [recipe_dir].into_iter().chain(args.wordlist_dir.iter().cloned()).collect();
+    options.wordlist_mode = args.wordlist_mode;
+    let charset_args = [&args.custom_charset1, &args.custom_charset2, &args.custom_charset3, &args.custom_charset4];
+    for (i, definition) in charset_args.into_iter().enumerate() {
+        let Some(definition) = definition else { continue };
+        match MaskCharType::expand_charset(definition, &options.custom_charsets) {
+            Ok(charset) => options.custom_charsets[i] = charset,
+            Err(e) => {
+                eprintln!("error: invalid custom charset {}: {}", i + 1, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let diagnostics = RecipeValidator::validate(&recipe, &options);
+    for diagnostic in &diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    if !diagnostics.is_empty() {
+        eprintln!("error: aborting due to {} previous error{}", diagnostics.len(), if diagnostics.len() == 1 { "" } else { "s" });
+        std::process::exit(1);
+    }
+    if args.check {
+        eprintln!("{}: no problems found", args.recipe);
+        return Ok(());
+    }
+
+    let mut candidate_gen = match CandidateGenerator::from_recipe(recipe, options) {
+        Ok(candidate_gen) => candidate_gen,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    for warning in candidate_gen.warnings() {
+        eprintln!("warning: {}", warning);
+    }
+
+    if args.count {
+        println!("{}", candidate_gen.keyspace());
+        return Ok(());
+    }
+
+    let num_threads = match args.threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n
+    };
+    if num_threads > 1 {
+        let split = candidate_gen.split_work(num_threads as u128 * UNITS_PER_THREAD);
+        // With a single unit, e.g. when the first step depends on the generated text, there is nothing to share
+        if split.units() > 1 {
+            return exit_on_error(parallel_generator::generate(&candidate_gen, &split, num_threads, !args.unordered,
+                                                              &mut std::io::stdout().lock()));
+        }
+    }
+
+    let mut finished = false;
+    if let Some(restore_path) = &args.restore {
+        let checkpoint = Checkpoint::read_from(restore_path)?;
+        if let Err(e) = candidate_gen.restore(&checkpoint) {
+            eprintln!("error: can't restore from {}: {}", restore_path, e);
+            std::process::exit(1);
+        }
+        finished = checkpoint.finished;
+    }
+    if let Some(limit) = args.limit {
+        candidate_gen.set_limit(limit);
+    }
+    if finished || !candidate_gen.skip_candidates(args.skip) {
+        return Ok(());
+    }
+
+    let mut writer = BufWriter::new(std::io::stdout());
+    let Some(checkpoint_path) = args.checkpoint.or(args.restore) else {
+        return exit_on_error(write_remaining(&mut candidate_gen, &mut writer));
+    };
 
-    let mut candidate_gen: CandidateGenerator = CandidateGenerator::from_recipe(recipe, Box::new(BufWriter::new(std::io::stdout())));
+    // Checkpoints are only written between candidates, so Ctrl+C just sets a flag
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_flag = interrupted.clone();
+    ctrlc::set_handler(move || handler_flag.store(true, Ordering::SeqCst))
+        .expect("Error setting Ctrl+C handler");
 
-    while candidate_gen.print_next() {}
+    let interval = Duration::from_secs(args.checkpoint_interval);
+    let mut last_checkpoint = Instant::now();
+    let mut num_printed: u64 = 0;
+    while candidate_gen.write_next(&mut writer).unwrap_or_else(|e| output_failed(e, &checkpoint_path)) {
+        num_printed += 1;
+        if !num_printed.is_multiple_of(CHECKPOINT_CHECK_PERIOD) { continue; }
+
+        let was_interrupted = interrupted.load(Ordering::SeqCst);
+        if was_interrupted || last_checkpoint.elapsed() >= interval {
+            // Output has to be flushed first so the checkpoint never gets ahead of it
+            writer.flush().unwrap_or_else(|e| output_failed(e, &checkpoint_path));
+            candidate_gen.checkpoint().write_to(&checkpoint_path)?;
+            last_checkpoint = Instant::now();
+        }
+        if was_interrupted {
+            eprintln!("Interrupted; progress saved to {}", checkpoint_path);
+            std::process::exit(130);
+        }
+    }
+
+    writer.flush().unwrap_or_else(|e| output_failed(e, &checkpoint_path));
+    // Only marked finished when every candidate was printed; stopping because of --limit can be continued with a new limit
+    candidate_gen.checkpoint().write_to(&checkpoint_path)?;
 
     Ok(())
 }
+
+// A closed pipe, e.g. from `| head`, is a normal way for the output to end
+fn exit_on_error(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        _ => Ok(())
+    }
+}
+
+fn write_remaining(candidate_gen: &mut CandidateGenerator, writer: &mut impl Write) -> io::Result<()> {
+    while candidate_gen.write_next(writer)? {}
+    writer.flush()
+}
+
+// Writing or reading failed; the candidates since the last checkpoint may not have been used, e.g. when Ctrl+C
+// also stopped the program reading them, so the last checkpoint is kept as it is
+fn output_failed(error: io::Error, checkpoint_path: &str) -> ! {
+    if Path::new(checkpoint_path).exists() {
+        eprintln!("error: {}; progress up to the last checkpoint is saved in {}", error, checkpoint_path);
+    } else {
+        eprintln!("error: {}", error);
+    }
+    std::process::exit(1);
+}
@@ -0,0 +1,126 @@
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+use crate::candidate_generator::{CandidateGenerator, WorkSplit};
+
+// More units than threads keeps threads busy when some units take longer than others
+pub const UNITS_PER_THREAD: u128 = 16;
+
+// Output is passed from the threads in chunks of about this many bytes
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Chunks each thread can have waiting before it has to wait for the output
+const CHUNKS_PER_THREAD: usize = 16;
+
+enum WorkerMessage {
+    Output(Vec<u8>),
+    UnitDone,
+    Error(io::Error)
+}
+
+// Writer for a thread's generator that sends what is written to it in chunks of whole lines,
+// so that unordered output from different threads never mixes within a line
+struct ChunkSender {
+    sender: SyncSender<WorkerMessage>,
+    pending: Vec<u8>
+}
+
+impl ChunkSender {
+    fn send(&mut self, chunk: Vec<u8>) -> io::Result<()> {
+        self.sender.send(WorkerMessage::Output(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "output closed"))
+    }
+}
+
+impl Write for ChunkSender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        if self.pending.len() >= CHUNK_SIZE && let Some(last_newline) = self.pending.iter().rposition(|b| *b == b'\n') {
+            let rest = self.pending.split_off(last_newline + 1);
+            let chunk = std::mem::replace(&mut self.pending, rest);
+            self.send(chunk)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() { return Ok(()); }
+        let chunk = std::mem::replace(&mut self.pending, Vec::with_capacity(CHUNK_SIZE));
+        self.send(chunk)
+    }
+}
+
+fn write_unit(candidate_gen: &mut CandidateGenerator, split: &WorkSplit, chunk_sender: &mut ChunkSender) -> io::Result<()> {
+    while candidate_gen.write_next_in_unit(split, chunk_sender)? {}
+    chunk_sender.flush()
+}
+
+// Generates candidates on several threads, each with its own copy of the generator; the copies share loaded
+// wordlists and rules and only keep their own positions
+// Ordered output is the same as with a single thread; unordered output lets threads write whole units as soon as they finish
+pub fn generate(candidate_gen: &CandidateGenerator, split: &WorkSplit, num_threads: usize, ordered: bool,
+                writer: &mut dyn Write) -> io::Result<()> {
+    let units = split.units();
+    let num_threads = (num_threads as u128).min(units) as usize;
+    let thread_gens: Vec<CandidateGenerator> = (0..num_threads).map(|_| candidate_gen.fork()).collect::<io::Result<_>>()?;
+
+    let run_worker = |mut candidate_gen: CandidateGenerator, sender: SyncSender<WorkerMessage>,
+                      mut next_unit: Box<dyn FnMut() -> Option<u128> + Send + '_>| {
+        // ChunkSender does the buffering
+        let mut chunk_sender = ChunkSender { sender: sender.clone(), pending: Vec::with_capacity(CHUNK_SIZE) };
+        while let Some(unit) = next_unit() {
+            if !candidate_gen.seek_unit(split, unit) { break; }
+            if let Err(e) = write_unit(&mut candidate_gen, split, &mut chunk_sender) {
+                // Errors from the generator, e.g. reading a wordlist, are passed on; a closed output only stops the thread
+                if candidate_gen.error().is_some() { let _ = sender.send(WorkerMessage::Error(e)); }
+                break;
+            }
+            if sender.send(WorkerMessage::UnitDone).is_err() { break; }
+        }
+    };
+
+    // Next unit for unordered output, where threads take whichever unit is next
+    let next_unit = Mutex::new(0u128);
+    thread::scope(|scope| {
+        if ordered {
+            // Units are handed out in turn, so the output can be read from each thread in turn
+            let mut receivers: Vec<Receiver<WorkerMessage>> = Vec::with_capacity(num_threads);
+            for (thread_idx, thread_gen) in thread_gens.into_iter().enumerate() {
+                let (sender, receiver) = mpsc::sync_channel(CHUNKS_PER_THREAD);
+                receivers.push(receiver);
+                let mut units_iter = (thread_idx as u128..units).step_by(num_threads);
+                scope.spawn(move || run_worker(thread_gen, sender, Box::new(move || units_iter.next())));
+            }
+            for unit in 0..units {
+                loop {
+                    match receivers[(unit % num_threads as u128) as usize].recv() {
+                        Ok(WorkerMessage::Output(chunk)) => writer.write_all(&chunk)?,
+                        Ok(WorkerMessage::UnitDone) => break,
+                        Ok(WorkerMessage::Error(e)) => return Err(e),
+                        Err(_) => return Err(io::Error::other("a generator thread stopped early"))
+                    }
+                }
+            }
+        } else {
+            let take_unit = || {
+                let mut next = next_unit.lock().unwrap();
+                (*next < units).then(|| { *next += 1; *next - 1 })
+            };
+            let (sender, receiver) = mpsc::sync_channel(CHUNKS_PER_THREAD * num_threads);
+            for thread_gen in thread_gens {
+                let sender = sender.clone();
+                scope.spawn(move || run_worker(thread_gen, sender, Box::new(take_unit)));
+            }
+            drop(sender);
+            for message in receiver {
+                match message {
+                    WorkerMessage::Output(chunk) => writer.write_all(&chunk)?,
+                    WorkerMessage::UnitDone => {}
+                    WorkerMessage::Error(e) => return Err(e)
+                }
+            }
+        }
+        writer.flush()
+    })
+}
@@ -1,54 +1,269 @@
-use crate::recipe_step::{CommonAttributes, GeneratorModifiers, Recipe};
+use crate::recipe_step::{CommonAttributes, GeneratorModifiers, Recipe, ReplaceMode, WordlistOptions};
 use crate::recipe_step::RecipeStep;
+use crate::candidate_generator::GeneratorOptions;
+use crate::items::mask::NUM_CUSTOM_CHARSETS;
 
 use std::default::Default;
+use std::fmt::{Display, Formatter};
 use logos::Logos;
+use regex::Regex;
 
 pub struct RecipeParser {}
 
 impl RecipeParser {
-    pub fn parse(recipe_text: String) -> Recipe {
-        // Split by newlines and commas
-        let recipe_steps_text = recipe_text
-            .lines()
-            .flat_map(|line| line.split(',').map(str::trim))
-            .filter(|s| !s.is_empty());
-
-        // println!("{recipe_steps_text:?}");
-
-        let recipe_steps = recipe_steps_text
-            .map(Self::parse_step)
-            .map(Result::unwrap)
-            .collect();
+    // Parses every step in the recipe, returning all errors found instead of stopping at the first one
+    pub fn parse(recipe_text: String) -> Result<Recipe, Vec<RecipeParseError>> {
+        Self::parse_with_options(recipe_text, &GeneratorOptions::default())
+    }
+
+    // Like parse, but also accepts the custom steps in the options
+    pub fn parse_with_options(recipe_text: String, options: &GeneratorOptions) -> Result<Recipe, Vec<RecipeParseError>> {
+        let mut recipe_steps = Vec::new();
+        let mut errors = Vec::new();
+
+        for (line_idx, full_line) in recipe_text.lines().enumerate() {
+            // No step starts with `#`, so a line starting with it is a comment even without a space (`#comment`)
+            let line = if full_line.trim_start().starts_with('#') { "" } else { Self::strip_comment(full_line) };
+            // Split by commas, keeping track of where each step starts in the line
+            let mut step_start = 0;
+            for mut step_text in Self::split_steps(line) {
+                // Charsets can contain `,`, ` #` and ` //`, so a charset step takes the rest of the line as it is
+                let rest_of_line = matches!(Self::step_type(step_text.trim()), "cs" | "charset");
+                if rest_of_line {
+                    step_text = &full_line[step_start..];
+                }
+                let trimmed = step_text.trim();
+                let step_offset = step_start + (step_text.len() - step_text.trim_start().len());
+                step_start += step_text.len() + 1;
+                if trimmed.is_empty() { continue; }
+
+                match Self::parse_step(trimmed, options) {
+                    Ok(step) => recipe_steps.push(step),
+                    Err(err) => errors.push(err.locate(line_idx + 1, full_line, step_offset))
+                }
+                if rest_of_line { break; }
+            }
+        }
 
         // println!("\n=== RECIPE ===");
         // for step in &recipe_steps {
         //     println!("{:?}", step)
         // }
 
-        recipe_steps
+        if errors.is_empty() { Ok(recipe_steps) } else { Err(errors) }
+    }
+
+    // Splits a line into steps at commas, except inside `[...]` options in the first token of a step
+    fn split_steps(line: &str) -> Vec<&str> {
+        let mut steps = Vec::new();
+        let mut step_start = 0;
+        let mut depth = 0;
+        let mut in_first_token = true;
+        let mut escaped = false;
+        for (i, c) in line.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if depth > 0 => escaped = true,
+                '[' if in_first_token => depth += 1,
+                ']' if depth > 0 => depth -= 1,
+                ',' if depth == 0 => {
+                    steps.push(&line[step_start..i]);
+                    step_start = i + 1;
+                    in_first_token = true;
+                }
+                c if c.is_whitespace() && depth == 0 && !line[step_start..i].trim().is_empty() => in_first_token = false,
+                _ => {}
+            }
+        }
+        steps.push(&line[step_start..]);
+        steps
     }
 
-    fn parse_step(step_text: &str) -> Result<RecipeStep, RecipeParseError> {
+    // Byte length of a `[...]` block at the start of the text, including brackets, or None if it isn't closed
+    // Brackets inside it (e.g. in a regex) have to be balanced or escaped with a backslash
+    fn bracket_block_len(text: &str) -> Option<usize> {
+        let mut depth = 0;
+        let mut escaped = false;
+        for (i, c) in text.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '[' => depth += 1,
+                ']' => {
+                    depth -= 1;
+                    if depth == 0 { return Some(i + 1); }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    // Options inside `wordlist[...]`, separated by commas outside of brackets and parentheses
+    // Offset is the byte offset of the options text within the step, for errors
+    fn parse_wordlist_options(options_text: &str, offset: usize) -> Result<WordlistOptions, RecipeParseError> {
+        let mut option_strs = Vec::new();
+        let (mut start, mut depth, mut escaped) = (0, 0, false);
+        for (i, c) in options_text.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '[' | '(' | '{' => depth += 1,
+                ']' | ')' | '}' => depth -= 1,
+                ',' if depth == 0 => {
+                    option_strs.push((start, &options_text[start..i]));
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        option_strs.push((start, &options_text[start..]));
+
+        let mut options = WordlistOptions::default();
+        for (option_start, option_str) in option_strs {
+            let option_offset = offset + option_start;
+            let value_offset = option_offset + option_str.find('=').map_or(0, |i| i + 1);
+            let invalid = || RecipeParseError::new(RecipeParseErrorKind::InvalidOption, option_offset, option_str);
+            let number = |value: &str| value.parse::<usize>()
+                .map_err(|_| RecipeParseError::new(RecipeParseErrorKind::InvalidNumber, value_offset, value));
+            let regex = |value: &str| Regex::new(value).map_err(|e| {
+                // Only keep the last line of the error, which describes the problem
+                let description = e.to_string().lines().last().unwrap_or_default().trim_start_matches("error: ").to_owned();
+                RecipeParseError::new(RecipeParseErrorKind::InvalidRegex(description), value_offset, value)
+            });
+            match option_str.split_once('=') {
+                Some(("min", value)) => options.min_len = Some(number(value)?),
+                Some(("max", value)) => options.max_len = Some(number(value)?),
+                Some(("charset", value)) if !value.is_empty() => options.charset = Some(value.to_owned()),
+                Some(("match", value)) => options.match_regex = Some(regex(value)?),
+                Some(("exclude", value)) => options.exclude_regex = Some(regex(value)?),
+                None if option_str == "unique" => options.unique = true,
+                _ => return Err(invalid())
+            }
+        }
+        Ok(options)
+    }
+
+    // Removes a `//` or `# ` comment from the end of a line
+    // Comments must start the line or follow whitespace, and `#` must be followed by whitespace
+    // so that IDs like `#3` or `#word` are not treated as comments
+    fn strip_comment(line: &str) -> &str {
+        let bytes = line.as_bytes();
+        for (i, c) in bytes.iter().enumerate() {
+            if i > 0 && !bytes[i - 1].is_ascii_whitespace() { continue; }
+            let next = bytes.get(i + 1);
+            let is_comment = match c {
+                b'/' => next == Some(&b'/'),
+                b'#' => next.is_none_or(u8::is_ascii_whitespace),
+                _ => false
+            };
+            if is_comment { return &line[..i]; }
+        }
+        line
+    }
+
+    // One of:
+    // - two characters (`a4`), which can also be `:` or `=` (`a:` replaces a with a colon)
+    // - any text on both sides of a colon (`ck:kk`, `o:()`)
+    // - a table entry with alternatives (`a=4@`), separated by `|` if they are longer than a character (`o=0|()`)
+    // The colon or equals sign is searched for after the first character so that `:` and `=` themselves can be replaced
+    fn parse_replacement(repl_str: &str) -> Option<Vec<(String, String)>> {
+        let mut chars = repl_str.chars();
+        let first = chars.next()?;
+        if let (Some(second), None) = (chars.next(), chars.next()) {
+            return Some(vec![(first.to_string(), second.to_string())]);
+        }
+        let first_len = first.len_utf8();
+        match repl_str[first_len..].find([':', '=']).map(|i| i + first_len) {
+            Some(sep) if repl_str[sep..].starts_with(':') => {
+                Some(vec![(repl_str[..sep].to_owned(), repl_str[sep + 1..].to_owned())])
+            }
+            Some(sep) => {
+                let (from, alternatives) = (&repl_str[..sep], &repl_str[sep + 1..]);
+                let alternatives: Vec<String> = if alternatives.contains('|') {
+                    alternatives.split('|').filter(|a| !a.is_empty()).map(str::to_owned).collect()
+                } else {
+                    alternatives.chars().map(String::from).collect()
+                };
+                if alternatives.is_empty() { return None; }
+                Some(alternatives.into_iter().map(|to| (from.to_owned(), to)).collect())
+            }
+            None => None
+        }
+    }
+
+    // Substitution table file: replacements in the same format as the replace step, separated by whitespace or new lines
+    pub fn parse_replacement_table(table_text: &str) -> Result<Vec<(String, String)>, String> {
+        let mut replacements = Vec::new();
+        for (line_idx, line) in table_text.lines().enumerate() {
+            for repl_str in Self::strip_comment(line).split_whitespace() {
+                let parsed = Self::parse_replacement(repl_str)
+                    .ok_or_else(|| format!("malformed replacement `{}` on line {}", repl_str, line_idx + 1))?;
+                replacements.extend(parsed);
+            }
+        }
+        Ok(replacements)
+    }
+
+    // The step name, before any modifiers or options
+    fn step_type(step_text: &str) -> &str {
+        let first_token = step_text.split_once(' ').map_or(step_text, |(first, _)| first);
+        first_token
+            .split_once(['+', '?', '#', '.', '[', '^'])
+            .map(|(a, b)| a)
+            .unwrap_or(first_token)
+    }
+
+    fn parse_step(step_text: &str, options: &GeneratorOptions) -> Result<RecipeStep, RecipeParseError> {
         let (first_token, remainder): (&str, &str) = step_text
             .split_once([' '])
             .unwrap_or((step_text, ""));
         let remainder = remainder.trim();
 
-        let step_type: &str = first_token
-            .split_once(['+', '?', '#', '.', '[', '^'])
-            .map(|(a, b)| a)
-            .unwrap_or(first_token);
+        let step_type = Self::step_type(first_token);
 
         // println!("Parsed step type for \"{step_text}\" is {step_type}");
 
         // Parse ID, classes, and optional modifier
         let mut attr: CommonAttributes = Default::default();
         let mut modifiers: GeneratorModifiers = Default::default();
-        let mut modifiers_lex = AttributeToken::lexer(first_token);
+        let mut modifiers_text = &first_token[step_type.len()..];
+
+        // Options in brackets come right after the step type
+        let mut wordlist_options = None;
+        if modifiers_text.starts_with('[') {
+            let options_len = Self::bracket_block_len(modifiers_text).ok_or_else(|| RecipeParseError::new(
+                RecipeParseErrorKind::UnclosedOptions, step_type.len(), modifiers_text
+            ))?;
+            let options_block = &modifiers_text[..options_len];
+            if !matches!(step_type, "w" | "word" | "wl" | "wordlist") {
+                return Err(RecipeParseError::new(RecipeParseErrorKind::InvalidOption, step_type.len(), options_block));
+            }
+            wordlist_options = Some(Self::parse_wordlist_options(&options_block[1..options_len - 1], step_type.len() + 1)?);
+            modifiers_text = &modifiers_text[options_len..];
+        }
+        let modifiers_offset = first_token.len() - modifiers_text.len();
+
+        let mut modifiers_lex = AttributeToken::lexer(modifiers_text);
         let mut first_modifier = true;
+        // +all is only valid for replace, so keep where it was to report it otherwise
+        let mut replace_all: Option<(usize, &str, ReplaceMode)> = None;
+        // Case, optional and hidden modifiers change a step's text, so keep the first one to report it on steps without text
+        let mut text_modifier: Option<(usize, &str)> = None;
         while let Some(result) = modifiers_lex.next() {
-            if let Ok(token) = result { match token {
+            let Ok(token) = result else {
+                // Report everything up to the next modifier as the bad modifier
+                let rest = &modifiers_text[modifiers_lex.span().start..];
+                let first_len = rest.chars().next().map_or(0, char::len_utf8);
+                let end = rest[first_len..].find(['+', '?', '#', '.', '[', '^']).map_or(rest.len(), |e| e + first_len);
+                return Err(RecipeParseError::new(
+                    RecipeParseErrorKind::InvalidModifier, modifiers_offset + modifiers_lex.span().start, &rest[..end]
+                ));
+            };
+            if matches!(token, AttributeToken::Modifiers | AttributeToken::Optional | AttributeToken::Hidden) && text_modifier.is_none() {
+                text_modifier = Some((modifiers_offset + modifiers_lex.span().start, modifiers_lex.slice()));
+            }
+            match token {
                 AttributeToken::ID => { attr.id = Some(modifiers_lex.slice().trim().to_owned()) }
                 AttributeToken::Class => { attr.classes.push(modifiers_lex.slice().trim().to_owned()) }
                 AttributeToken::Optional => { modifiers.optional = true }
@@ -64,29 +279,107 @@ impl RecipeParser {
                     if modifier_chars.contains('t') { modifiers.case.titlecase = true }
                 }
                 AttributeToken::Hidden => { modifiers.hidden = true }
-            } }
+                AttributeToken::AllCombinations => {
+                    let max_substitutions = modifiers_lex.slice()["+all".len()..].parse::<usize>().ok();
+                    replace_all = Some((modifiers_offset + modifiers_lex.span().start, modifiers_lex.slice(), ReplaceMode::All { max_substitutions }));
+                }
+            }
+        }
+        if let Some((offset, token, _)) = replace_all && !matches!(step_type, "rep" | "replace") {
+            return Err(RecipeParseError::new(RecipeParseErrorKind::InvalidModifier, offset, token));
+        }
+
+        // Remaining tokens with their byte offsets in the step text
+        let remaining_tokens: Vec<(usize, &str)> = remainder
+            .split_whitespace()
+            .map(|s| (s.as_ptr() as usize - step_text.as_ptr() as usize, s))
+            .collect();
+        let missing = |expected: &'static str| RecipeParseError::new(
+            RecipeParseErrorKind::MissingArgument(expected), step_text.len(), ""
+        );
+        let require = |expected: &'static str| if remainder.is_empty() { Err(missing(expected)) } else { Ok(remainder.to_owned()) };
+        let parse_number = |(offset, token): (usize, &str)| token.parse::<usize>()
+            .map_err(|_| RecipeParseError::new(RecipeParseErrorKind::InvalidNumber, offset, token));
+        let target_list = |expected: &'static str| if remaining_tokens.is_empty() { Err(missing(expected)) } else {
+            Ok(remaining_tokens.iter().map(|(_, s)| (*s).to_owned()).collect())
+        };
+
+        if let Some(custom_step) = options.custom_steps.get(step_type) {
+            // Format: <name> [target] <arguments>
+            let target_id = if custom_step.takes_target {
+                Some(remaining_tokens.first().ok_or_else(|| missing("a target #ID"))?.1.to_owned())
+            } else {
+                None
+            };
+            let args_offset = remaining_tokens.get(target_id.is_some() as usize).map_or(step_text.len(), |(offset, _)| *offset);
+            let args_text = &step_text[args_offset..];
+            let args = custom_step.parse_args(args_text, options)
+                .map_err(|e| RecipeParseError::new(RecipeParseErrorKind::InvalidArguments(e), args_offset, args_text))?;
+            return Ok(RecipeStep::Custom { step: custom_step.clone(), target_id, args, attr, modifiers });
         }
 
         match step_type {
-            "w" | "word" | "wl" | "wordlist" => Ok(RecipeStep::Wordlist { filename: remainder.to_owned(), attr, modifiers }),
-            "m" | "mask" => Ok(RecipeStep::Mask { mask: remainder.to_owned(), attr, modifiers }),
-            "mi" | "maskinc" | "maskincremental" => Ok(RecipeStep::MaskIncremental { mask: remainder.to_owned(), attr, modifiers }),
+            "w" | "word" | "wl" | "wordlist" => Ok(RecipeStep::Wordlist {
+                filename: require("a wordlist filename")?, options: wordlist_options.unwrap_or_default(), attr, modifiers
+            }),
+            "m" | "mask" => Ok(RecipeStep::Mask { mask: require("a mask")?, attr, modifiers }),
+            "mi" | "maskinc" | "maskincremental" => {
+                // Format: maskinc <mask> [min length] [max length]
+                let mask = remaining_tokens.first().ok_or_else(|| missing("a mask"))?.1.to_owned();
+                let min_len = remaining_tokens.get(1).copied().map(parse_number).transpose()?;
+                let max_len = remaining_tokens.get(2).copied().map(parse_number).transpose()?;
+                Ok(RecipeStep::MaskIncremental { mask, min_len, max_len, attr, modifiers })
+            }
             "c" | "const" | "constant" => Ok(RecipeStep::Constant { value: remainder.to_owned(), attr, modifiers }),
-            "d" | "dup" | "duplicate" => Ok(RecipeStep::Duplicate { target_id: remainder.to_owned(), attr, modifiers }),
-            "l" | "loc" | "location" => Ok(RecipeStep::Location { attr }),
-            "r" | "rearr" | "rearrange" => Ok(RecipeStep::Rearrange { target_list: remainder.split_whitespace().map(|s| s.trim().to_owned()).collect() }),
-            "cc" | "concat" => Ok(RecipeStep::Concat { target_list: remainder.split_whitespace().map(|s| s.trim().to_owned()).collect(), attr, modifiers }),
+            "d" | "dup" | "duplicate" => Ok(RecipeStep::Duplicate { target_id: require("a target #ID")?, attr, modifiers }),
+            "l" | "loc" | "location" => {
+                if let Some((offset, token)) = text_modifier {
+                    return Err(RecipeParseError::new(RecipeParseErrorKind::InvalidModifier, offset, token));
+                }
+                Ok(RecipeStep::Location { attr })
+            }
+            "i" | "ins" | "insert" => {
+                let location_id = remaining_tokens.first().ok_or_else(|| missing("a location #ID"))?.1.to_owned();
+                if remaining_tokens.len() < 2 { return Err(missing("a list of #IDs or .classes to insert")); }
+                Ok(RecipeStep::Insert { location_id, target_list: remaining_tokens[1..].iter().map(|(_, s)| (*s).to_owned()).collect() })
+            }
+            "r" | "rearr" | "rearrange" => Ok(RecipeStep::Rearrange { target_list: target_list("a list of #IDs or .classes")? }),
+            "cc" | "concat" => Ok(RecipeStep::Concat { target_list: target_list("a list of #IDs or .classes")?, attr, modifiers }),
             "rep" | "replace" => {
-                let remaining_tokens: Vec<String> = remainder.split_whitespace().map(|s| s.trim().to_owned()).collect();
-                let target_id = remaining_tokens[0].clone();
-                let mut repl_chars: Vec<(char, char)> = Vec::new();
-                for repl_str in &remaining_tokens[1..] {
-                    repl_chars.push((repl_str.chars().nth(0).unwrap(), repl_str.chars().nth(1).unwrap()));
+                let target_id = remaining_tokens.first().ok_or_else(|| missing("a target #ID"))?.1.to_owned();
+                if remaining_tokens.len() < 2 { return Err(missing("at least one replacement pair")); }
+                let mut replacements = Vec::new();
+                let mut table_files = Vec::new();
+                for (offset, repl_str) in &remaining_tokens[1..] {
+                    // @file loads a substitution table; two characters starting with @ are still a pair
+                    if let Some(filename) = repl_str.strip_prefix('@') && filename.chars().count() > 1 {
+                        table_files.push(filename.to_owned());
+                        continue;
+                    }
+                    let parsed = Self::parse_replacement(repl_str)
+                        .ok_or_else(|| RecipeParseError::new(RecipeParseErrorKind::MalformedReplacement, *offset, repl_str))?;
+                    replacements.extend(parsed);
                 }
-                Ok(RecipeStep::ReplaceChar { target_id, replacements: repl_chars, attr, modifiers })
+                let mode = replace_all.map_or(ReplaceMode::Single, |(_, _, mode)| mode);
+                Ok(RecipeStep::Replace { target_id, replacements, table_files, mode, attr, modifiers })
+            }
+            "ru" | "rule" | "rules" => {
+                // Format: rules <target> <rule file>
+                let target_id = remaining_tokens.first().ok_or_else(|| missing("a target #ID"))?.1.to_owned();
+                let (filename_offset, _) = remaining_tokens.get(1).ok_or_else(|| missing("a rule filename"))?;
+                Ok(RecipeStep::Rules { target_id, filename: step_text[*filename_offset..].to_owned(), attr, modifiers })
+            }
+            "cs" | "charset" => {
+                // Format: charset ?1 <definition>
+                let (slot_offset, slot_token) = *remaining_tokens.first().ok_or_else(|| missing("a custom charset `?1` to `?4`"))?;
+                let slot = slot_token.strip_prefix('?').unwrap_or(slot_token).parse::<usize>().ok()
+                    .filter(|n| (1..=NUM_CUSTOM_CHARSETS).contains(n))
+                    .ok_or_else(|| RecipeParseError::new(RecipeParseErrorKind::InvalidCharsetSlot, slot_offset, slot_token))?;
+                let (definition_offset, _) = remaining_tokens.get(1).ok_or_else(|| missing("a charset definition"))?;
+                Ok(RecipeStep::Charset { slot: slot - 1, definition: step_text[*definition_offset..].to_owned() })
             }
             "sp" | "space" => Ok(RecipeStep::Constant { value: " ".to_owned(), attr, modifiers }),
-            _ => Err(RecipeParseError)
+            _ => Err(RecipeParseError::new(RecipeParseErrorKind::UnknownStepType, 0, step_type))
         }
 
     }
@@ -103,8 +396,99 @@ enum AttributeToken {
     #[token("?")]
     Optional,
     #[token("^")]
-    Hidden
+    Hidden,
+    #[regex(r"\+all\d*")]
+    AllCombinations
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecipeParseErrorKind {
+    UnknownStepType,
+    InvalidModifier,
+    MissingArgument(&'static str),
+    MalformedReplacement,
+    InvalidNumber,
+    InvalidCharsetSlot,
+    InvalidOption,
+    UnclosedOptions,
+    InvalidRegex(String),
+    InvalidArguments(String)
 }
 
 #[derive(Debug, Clone)]
-struct RecipeParseError;
\ No newline at end of file
+pub struct RecipeParseError {
+    pub kind: RecipeParseErrorKind,
+    pub line: usize,        // 1-based
+    pub column: usize,      // 1-based, counted in characters
+    pub token: String,
+    pub line_text: String
+}
+
+impl RecipeParseError {
+    // Offset is the byte offset of the token within the step; `locate` turns it into a line and column
+    fn new(kind: RecipeParseErrorKind, offset: usize, token: &str) -> Self {
+        RecipeParseError { kind, line: 0, column: offset, token: token.to_owned(), line_text: String::new() }
+    }
+
+    fn locate(mut self, line: usize, line_text: &str, step_offset: usize) -> Self {
+        let byte_offset = step_offset + self.column;
+        self.column = line_text[..byte_offset].chars().count() + 1;
+        self.line = line;
+        self.line_text = line_text.to_owned();
+        self
+    }
+
+    pub fn message(&self) -> String {
+        match &self.kind {
+            RecipeParseErrorKind::UnknownStepType => format!("unknown step type `{}`", self.token),
+            RecipeParseErrorKind::InvalidModifier => format!("invalid modifier `{}`", self.token),
+            RecipeParseErrorKind::MissingArgument(_) => "missing argument".to_owned(),
+            RecipeParseErrorKind::MalformedReplacement => format!("malformed replacement `{}`", self.token),
+            RecipeParseErrorKind::InvalidNumber => format!("invalid number `{}`", self.token),
+            RecipeParseErrorKind::InvalidCharsetSlot => format!("invalid custom charset `{}`", self.token),
+            RecipeParseErrorKind::InvalidOption => format!("invalid option `{}`", self.token),
+            RecipeParseErrorKind::UnclosedOptions => "unclosed `[` in step options".to_owned(),
+            RecipeParseErrorKind::InvalidRegex(description) => format!("invalid regex `{}`: {}", self.token, description),
+            RecipeParseErrorKind::InvalidArguments(description) => format!("invalid arguments: {}", description)
+        }
+    }
+
+    pub fn expected(&self) -> String {
+        match &self.kind {
+            RecipeParseErrorKind::UnknownStepType =>
+                "expected a step type such as `wordlist`, `mask`, `constant` or `replace`".to_owned(),
+            RecipeParseErrorKind::InvalidModifier =>
+                "expected `+` case modifiers (u, l, o, t), `?`, `^`, `#id` or `.class` (`+all` is only for replace, location only takes `#id` and `.class`)".to_owned(),
+            RecipeParseErrorKind::MissingArgument(expected) => format!("expected {}", expected),
+            RecipeParseErrorKind::MalformedReplacement =>
+                "expected a pair of characters such as `a4`, `from:to` such as `ck:kk`, alternatives such as `a=4@`, or `@file`".to_owned(),
+            RecipeParseErrorKind::InvalidNumber => "expected a number".to_owned(),
+            RecipeParseErrorKind::InvalidCharsetSlot => format!("expected a custom charset `?1` to `?{}`", NUM_CUSTOM_CHARSETS),
+            RecipeParseErrorKind::InvalidOption =>
+                "expected `min=N`, `max=N`, `charset=CHARSET`, `match=REGEX`, `exclude=REGEX` or `unique` (options are only for wordlist)".to_owned(),
+            RecipeParseErrorKind::UnclosedOptions => "expected `]` (options can't contain spaces)".to_owned(),
+            RecipeParseErrorKind::InvalidRegex(_) => "expected a regular expression".to_owned(),
+            RecipeParseErrorKind::InvalidArguments(_) => "expected arguments accepted by this step".to_owned()
+        }
+    }
+
+    // Multi-line report in the style of rustc, pointing at the offending token
+    pub fn report(&self, source_name: &str) -> String {
+        let line_num = self.line.to_string();
+        let gutter = " ".repeat(line_num.len());
+        let underline = "^".repeat(self.token.chars().count().max(1));
+        format!(
+            "error: {}\n{gutter}--> {}:{}:{}\n{gutter} |\n{line_num} | {}\n{gutter} | {}{} {}\n",
+            self.message(), source_name, self.line, self.column, self.line_text,
+            " ".repeat(self.column - 1), underline, self.expected()
+        )
+    }
+}
+
+impl Display for RecipeParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message())
+    }
+}
+
+impl std::error::Error for RecipeParseError {}
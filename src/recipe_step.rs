@@ -1,36 +1,104 @@
+use regex::Regex;
 use strum_macros::EnumIter;
+use crate::custom_step::{CustomStep, CustomStepArgs};
 use crate::recipe_step::RecipeStep::*;
 
 pub type Recipe = Vec<RecipeStep>;
 
 type StepID = String;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum RecipeStep {
     // Generators
-    Wordlist { filename: String, attr: CommonAttributes, modifiers: GeneratorModifiers },
+    Wordlist { filename: String, options: WordlistOptions, attr: CommonAttributes, modifiers: GeneratorModifiers },
     Mask { mask: String, attr: CommonAttributes, modifiers: GeneratorModifiers  },
-    MaskIncremental { mask: String, attr: CommonAttributes, modifiers: GeneratorModifiers  },
+    MaskIncremental { mask: String, min_len: Option<usize>, max_len: Option<usize>, attr: CommonAttributes, modifiers: GeneratorModifiers  },
     Constant { value: String, attr: CommonAttributes, modifiers: GeneratorModifiers  },
     Duplicate { target_id: StepID, attr: CommonAttributes, modifiers: GeneratorModifiers  },
 
     // Index
     Location { attr: CommonAttributes },
+    Insert { location_id: StepID, target_list: Vec<StepID> },
+
+    // Directive
+    Charset { slot: usize, definition: String },
 
     // Operation
     Rearrange { target_list: Vec<StepID> },
     Concat { target_list: Vec<StepID>, attr: CommonAttributes, modifiers: GeneratorModifiers },
     
-    ReplaceChar { target_id: StepID, replacements: Vec<(char, char)>, attr: CommonAttributes, modifiers: GeneratorModifiers }
+    Replace { target_id: StepID, replacements: Vec<(String, String)>, table_files: Vec<String>, mode: ReplaceMode, attr: CommonAttributes, modifiers: GeneratorModifiers },
+    Rules { target_id: StepID, filename: String, attr: CommonAttributes, modifiers: GeneratorModifiers },
+
+    // Registered by a library user
+    Custom { step: CustomStep, target_id: Option<StepID>, args: CustomStepArgs, attr: CommonAttributes, modifiers: GeneratorModifiers }
 }
 
-#[derive(Default, Debug)]
+impl RecipeStep {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Wordlist { .. } => "wordlist",
+            Mask { .. } => "mask",
+            MaskIncremental { .. } => "maskinc",
+            Constant { .. } => "constant",
+            Duplicate { .. } => "duplicate",
+            Location { .. } => "location",
+            Insert { .. } => "insert",
+            Charset { .. } => "charset",
+            Rearrange { .. } => "rearrange",
+            Concat { .. } => "concat",
+            Replace { .. } => "replace",
+            Rules { .. } => "rules",
+            Custom { step, .. } => step.name
+        }
+    }
+
+    // Attributes of steps that create a text segment which can be referenced later
+    pub fn attr(&self) -> Option<&CommonAttributes> {
+        match self {
+            Wordlist { attr, .. } | Mask { attr, .. } | MaskIncremental { attr, .. } | Constant { attr, .. } |
+            Duplicate { attr, .. } | Location { attr } | Concat { attr, .. } | Replace { attr, .. } |
+            Rules { attr, .. } | Custom { attr, .. } => Some(attr),
+            Insert { .. } | Rearrange { .. } | Charset { .. } => None
+        }
+    }
+
+    pub fn modifiers(&self) -> Option<&GeneratorModifiers> {
+        match self {
+            Wordlist { modifiers, .. } | Mask { modifiers, .. } | MaskIncremental { modifiers, .. } | Constant { modifiers, .. } |
+            Duplicate { modifiers, .. } | Concat { modifiers, .. } | Replace { modifiers, .. } | Rules { modifiers, .. } |
+            Custom { modifiers, .. } => Some(modifiers),
+            Location { .. } | Insert { .. } | Rearrange { .. } | Charset { .. } => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplaceMode {
+    // Original text, then one replacement at a time
+    Single,
+    // Every combination of replacements, optionally limited to a number of replacements at once
+    All { max_substitutions: Option<usize> }
+}
+
+// Filters from `wordlist[...]`; words that don't pass them are skipped while reading
+#[derive(Default, Debug, Clone)]
+pub struct WordlistOptions {
+    pub min_len: Option<usize>,         // In characters
+    pub max_len: Option<usize>,
+    pub charset: Option<String>,        // Charset definition like in the charset step, expanded when generating
+    pub match_regex: Option<Regex>,
+    pub exclude_regex: Option<Regex>,
+    pub unique: bool
+}
+
+#[derive(Default, Debug, Clone)]
 pub struct CommonAttributes {
     pub id: Option<String>,
     pub classes: Vec<String>
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CaseModifiers {
     pub titlecase: bool,
     pub uppercase: bool,
@@ -50,7 +118,7 @@ impl Default for CaseModifiers {
 }
 
 impl CaseModifiers {
-    pub fn includes_case(&self, case: CaseModifierType) -> bool {
+    pub fn includes_case(&self, case: &CaseModifierType) -> bool {
         match case {
             CaseModifierType::OriginalCase => self.originalcase,
             CaseModifierType::Lowercase => self.lowercase,
@@ -60,23 +128,13 @@ impl CaseModifiers {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct GeneratorModifiers {
     pub case: CaseModifiers,
     pub optional: bool,
     pub hidden: bool
 }
 
-impl Default for GeneratorModifiers {
-    fn default() -> Self {
-        GeneratorModifiers {
-            case: CaseModifiers::default(),
-            optional: false,
-            hidden: false
-        }
-    }
-}
-
 #[derive(EnumIter, PartialEq)]
 pub enum CaseModifierType {
     OriginalCase,
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use crate::candidate_generator::GeneratorOptions;
+use crate::items::mask::{CustomCharsets, MaskCharType};
+use crate::recipe_parser::RecipeParser;
+use crate::recipe_step::{Recipe, RecipeStep};
+use crate::recipe_step::RecipeStep::*;
+
+// Checks a parsed recipe for problems that would otherwise only show up while building the generator
+pub struct RecipeValidator<'a> {
+    recipe: &'a Recipe,
+    // #ID or .class -> step number where it is first defined
+    defined_ids: HashMap<String, usize>,
+    defined_classes: HashMap<String, usize>,
+    all_ids: HashMap<String, usize>,
+    all_classes: HashMap<String, usize>,
+    class_steps: HashMap<String, Vec<usize>>,
+    // Step number -> why its text is no longer part of the output, so insert can't use it as a location or bring it back
+    removed_steps: HashMap<usize, String>,
+    custom_charsets: CustomCharsets,
+    options: &'a GeneratorOptions,
+    diagnostics: Vec<RecipeDiagnostic>
+}
+
+#[derive(Debug, Clone)]
+pub struct RecipeDiagnostic {
+    pub step_num: usize,            // 1-based, same as the default #ID of the step
+    pub step_name: &'static str,
+    pub message: String
+}
+
+impl<'a> RecipeValidator<'a> {
+    pub fn validate(recipe: &'a Recipe, options: &'a GeneratorOptions) -> Vec<RecipeDiagnostic> {
+        let mut validator = RecipeValidator {
+            recipe,
+            defined_ids: HashMap::new(),
+            defined_classes: HashMap::new(),
+            all_ids: HashMap::new(),
+            all_classes: HashMap::new(),
+            class_steps: HashMap::new(),
+            removed_steps: HashMap::new(),
+            custom_charsets: options.custom_charsets.clone(),
+            options,
+            diagnostics: Vec::new()
+        };
+        validator.collect_all_tags();
+        for (i, step) in recipe.iter().enumerate() {
+            validator.check_step(i + 1, step);
+        }
+        validator.diagnostics
+    }
+
+    // Gather every tag in the recipe so that forward references can be told apart from unknown ones
+    fn collect_all_tags(&mut self) {
+        for (i, step) in self.recipe.iter().enumerate() {
+            let step_num = i + 1;
+            if let Some(attr) = step.attr() {
+                self.all_ids.entry(format!("#{}", step_num)).or_insert(step_num);
+                if let Some(id) = &attr.id { self.all_ids.entry(id.clone()).or_insert(step_num); }
+                for class in &attr.classes { self.all_classes.entry(class.clone()).or_insert(step_num); }
+            }
+        }
+    }
+
+    fn check_step(&mut self, step_num: usize, step: &RecipeStep) {
+        let step_name = step.name();
+        match step {
+            Mask { mask, .. } => {
+                if let Err(e) = MaskCharType::parse_mask(mask, &self.custom_charsets) {
+                    self.error(step_num, step_name, format!("{} in mask `{}`", e, mask));
+                }
+            }
+            MaskIncremental { mask, min_len, max_len, .. } => {
+                let mask_len = match MaskCharType::parse_mask(mask, &self.custom_charsets) {
+                    Ok(positions) => Some(positions.len()),
+                    Err(e) => {
+                        self.error(step_num, step_name, format!("{} in mask `{}`", e, mask));
+                        None
+                    }
+                };
+                if *min_len == Some(0) {
+                    self.error(step_num, step_name, "min length must be at least 1".to_owned());
+                }
+                if let (Some(min), Some(max)) = (min_len, max_len) && min > max {
+                    self.error(step_num, step_name, format!("min length {} is greater than max length {}", min, max));
+                }
+                if let (Some(min), Some(mask_len)) = (min_len, mask_len) && *min > mask_len {
+                    self.error(step_num, step_name, format!("min length {} is longer than the mask ({} characters)", min, mask_len));
+                }
+            }
+            Charset { slot, definition } => {
+                match MaskCharType::expand_charset(definition, &self.custom_charsets) {
+                    Ok(charset) => self.custom_charsets[*slot] = charset,
+                    Err(e) => self.error(step_num, step_name, e)
+                }
+            }
+            Replace { target_id, replacements, table_files, .. } => {
+                self.check_id_reference(step_num, step_name, target_id);
+                let mut num_replacements = Some(replacements.len());
+                for filename in table_files {
+                    let table = self.options.resolve_file(filename)
+                        .and_then(|path| std::fs::read_to_string(&path).map_err(|e| format!("can't read substitution table {}: {}", path, e)))
+                        .and_then(|text| RecipeParser::parse_replacement_table(&text)
+                            .map_err(|e| format!("invalid substitution table {}: {}", filename, e)));
+                    match table {
+                        Ok(table) => num_replacements = num_replacements.map(|n| n + table.len()),
+                        Err(e) => {
+                            self.error(step_num, step_name, e);
+                            num_replacements = None;
+                        }
+                    }
+                }
+                // Only known when every table could be read
+                if num_replacements == Some(0) {
+                    self.error(step_num, step_name, "no replacements, the substitution tables are empty".to_owned());
+                }
+            }
+            Rules { target_id, filename, .. } => {
+                self.check_id_reference(step_num, step_name, target_id);
+                self.check_file(step_num, step_name, filename);
+            }
+            Wordlist { filename, options, .. } => {
+                if let Err(e) = self.options.resolve_wordlist(filename) {
+                    self.error(step_num, step_name, e);
+                }
+                if let Some(definition) = &options.charset
+                    && let Err(e) = MaskCharType::expand_charset(definition, &self.custom_charsets) {
+                    self.error(step_num, step_name, format!("{} in charset option `{}`", e, definition));
+                }
+                if let (Some(min), Some(max)) = (options.min_len, options.max_len) && min > max {
+                    self.error(step_num, step_name, format!("min length {} is greater than max length {}", min, max));
+                }
+            }
+            Duplicate { target_id, .. } | Custom { target_id: Some(target_id), .. } => {
+                self.check_id_reference(step_num, step_name, target_id);
+            }
+            Insert { location_id, target_list } => {
+                self.check_id_reference(step_num, step_name, location_id);
+                if let Some(location_step) = self.defined_ids.get(location_id)
+                    && let Some(reason) = self.removed_steps.get(location_step) {
+                    self.error(step_num, step_name,
+                               format!("can't insert at `{}`, its text is no longer part of the output ({})", location_id, reason));
+                }
+                for tag in target_list {
+                    self.check_reference(step_num, step_name, tag);
+                    // Inserting would output the text twice, or bring back hidden text
+                    for target_step in self.tag_steps(tag) {
+                        if let Some(reason) = self.removed_steps.get(&target_step) {
+                            let message = format!("can't insert `{}`, the text of step {} is no longer part of the output ({})",
+                                                  tag, target_step, reason);
+                            self.error(step_num, step_name, message);
+                        }
+                    }
+                }
+            }
+            Rearrange { target_list } | Concat { target_list, .. } => {
+                for tag in target_list { self.check_reference(step_num, step_name, tag); }
+            }
+            Constant { .. } | Location { .. } | Custom { target_id: None, .. } => {}
+        }
+
+        // Steps that take the text of others out of the output
+        match step {
+            Replace { target_id, .. } | Rules { target_id, .. } | Custom { target_id: Some(target_id), .. } =>
+                self.mark_removed(target_id, format!("used by step {}", step_num)),
+            Concat { target_list, .. } => {
+                for tag in target_list { self.mark_removed(tag, format!("used by step {}", step_num)); }
+            }
+            _ => {}
+        }
+        if step.modifiers().is_some_and(|modifiers| modifiers.hidden) {
+            self.removed_steps.insert(step_num, "hidden".to_owned());
+        }
+
+        // Define tags after checking references, since a step can't refer to itself
+        if let Some(attr) = step.attr() {
+            let default_id = format!("#{}", step_num);
+            if let Some(prev_step) = self.defined_ids.insert(default_id.clone(), step_num) {
+                self.error(step_num, step_name,
+                           format!("default ID `{}` of this step is already used by step {}", default_id, prev_step));
+            }
+            if let Some(id) = &attr.id && *id != default_id
+                && let Some(prev_step) = self.defined_ids.insert(id.clone(), step_num) {
+                self.error(step_num, step_name, format!("duplicate ID `{}`, already used by step {}", id, prev_step));
+            }
+            for class in &attr.classes {
+                self.defined_classes.entry(class.clone()).or_insert(step_num);
+                self.class_steps.entry(class.clone()).or_default().push(step_num);
+            }
+        }
+    }
+
+    // Steps an #ID or .class defined so far refers to
+    fn tag_steps(&self, tag: &str) -> Vec<usize> {
+        let steps = match tag.chars().next() {
+            Some('#') => self.defined_ids.get(tag).map(|step| vec![*step]),
+            Some('.') => self.class_steps.get(tag).cloned(),
+            _ => None
+        };
+        steps.unwrap_or_default()
+    }
+
+    fn mark_removed(&mut self, tag: &str, reason: String) {
+        for step in self.tag_steps(tag) {
+            self.removed_steps.entry(step).or_insert_with(|| reason.clone());
+        }
+    }
+
+    fn check_file(&mut self, step_num: usize, step_name: &'static str, filename: &str) {
+        if let Err(e) = self.options.resolve_file(filename) {
+            self.error(step_num, step_name, e);
+        }
+    }
+
+    // References that have to point to a single step
+    fn check_id_reference(&mut self, step_num: usize, step_name: &'static str, tag: &str) {
+        if tag.starts_with('#') {
+            self.check_reference(step_num, step_name, tag);
+        } else {
+            self.error(step_num, step_name, format!("invalid reference `{}`, expected an #ID", tag));
+        }
+    }
+
+    fn check_reference(&mut self, step_num: usize, step_name: &'static str, tag: &str) {
+        let (defined, all, kind) = if tag.starts_with('#') {
+            (&self.defined_ids, &self.all_ids, "ID")
+        } else if tag.starts_with('.') {
+            (&self.defined_classes, &self.all_classes, "class")
+        } else {
+            self.error(step_num, step_name, format!("invalid reference `{}`, expected an #ID or .class", tag));
+            return;
+        };
+
+        if defined.contains_key(tag) { return; }
+        let message = match all.get(tag) {
+            Some(later_step) if kind == "class" =>
+                format!("class `{}` has no steps yet; its first step is step {}", tag, later_step),
+            Some(later_step) => format!("forward reference to `{}`, which is defined by step {}", tag, later_step),
+            None => format!("unknown {} `{}`", kind, tag)
+        };
+        self.error(step_num, step_name, message);
+    }
+
+    fn error(&mut self, step_num: usize, step_name: &'static str, message: String) {
+        self.diagnostics.push(RecipeDiagnostic { step_num, step_name, message });
+    }
+}
+
+impl Display for RecipeDiagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "error: step {} (`{}`): {}", self.step_num, self.step_name, self.message)
+    }
+}
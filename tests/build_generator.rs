@@ -0,0 +1,63 @@
+use password_chef::{build_generator, generator_from_str, CandidateGenerator, GeneratorOptions, RecipeError};
+use password_chef::items::wordlist::WordlistMode;
+use password_chef::recipe_parser::RecipeParser;
+
+#[test]
+fn missing_files_are_errors() {
+    let error = generator_from_str("wordlist no-such-file.txt", GeneratorOptions::default()).err().unwrap();
+    assert!(matches!(error, RecipeError::Invalid(_)));
+    // Without the validator, the generator reports it too
+    let recipe = RecipeParser::parse("constant a\nrules #1 no-such-file.rule".to_owned()).unwrap();
+    let error = CandidateGenerator::from_recipe(recipe, GeneratorOptions::default()).err().unwrap();
+    assert!(error.contains("no-such-file.rule"), "{}", error);
+}
+
+#[test]
+fn invalid_masks_are_errors() {
+    for recipe in ["mask ?1", "mask ?x", "maskinc ?d?d 3 1", "maskinc ?d?d 0", "maskinc ?d?d 3"] {
+        let parsed = RecipeParser::parse(recipe.to_owned()).unwrap();
+        assert!(CandidateGenerator::from_recipe(parsed, GeneratorOptions::default()).is_err(), "{}", recipe);
+    }
+    // A max length past the end of the mask is the whole mask
+    let parsed = RecipeParser::parse("maskinc ?d?d 2 5".to_owned()).unwrap();
+    assert_eq!(CandidateGenerator::from_recipe(parsed, GeneratorOptions::default()).unwrap().count(), 100);
+}
+
+#[test]
+fn inserting_text_that_left_the_output_is_an_error() {
+    for recipe in ["constant#a A\nconstant#b B\nconcat #a #b\nlocation#l\ninsert #l #a",
+                   "constant^#a A\nconstant B\nlocation#l\ninsert #l #a"] {
+        assert!(matches!(generator_from_str(recipe, GeneratorOptions::default()), Err(RecipeError::Invalid(_))), "{}", recipe);
+        let parsed = RecipeParser::parse(recipe.to_owned()).unwrap();
+        assert!(CandidateGenerator::from_recipe(parsed, GeneratorOptions::default()).is_err(), "{}", recipe);
+    }
+    let generator = generator_from_str("constant#a A\nconstant B\nlocation#l\ninsert #l #a", GeneratorOptions::default()).unwrap();
+    assert_eq!(generator.collect::<Vec<_>>(), ["BA"]);
+}
+
+#[test]
+fn skipped_rules_are_warnings() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("test.rule"), "u\n!!\n$1\n").unwrap();
+    let options = GeneratorOptions { search_dirs: vec![dir.path().to_path_buf()], ..GeneratorOptions::default() };
+    let recipe = RecipeParser::parse("constant ab\nrules #1 test.rule".to_owned()).unwrap();
+    let generator = build_generator(recipe, options).unwrap();
+    assert_eq!(generator.warnings().len(), 1);
+    assert!(generator.warnings()[0].contains("line 2"), "{}", generator.warnings()[0]);
+    assert_eq!(generator.collect::<Vec<_>>(), ["AB", "ab1"]);
+}
+
+#[test]
+fn fork_continues_from_the_same_candidate() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("words.txt"), "a\nb\nb\nc\n").unwrap();
+    for wordlist_mode in [WordlistMode::Memory, WordlistMode::Stream] {
+        let options = GeneratorOptions { search_dirs: vec![dir.path().to_path_buf()], wordlist_mode, ..GeneratorOptions::default() };
+        let mut generator = generator_from_str("wordlist[unique] words.txt\nmask ?d", options).unwrap();
+        generator.by_ref().take(13).count();
+        let fork = generator.fork().unwrap();
+        let rest: Vec<String> = generator.collect();
+        assert_eq!(rest.len(), 17);
+        assert_eq!(fork.collect::<Vec<_>>(), rest, "{:?}", wordlist_mode);
+    }
+}
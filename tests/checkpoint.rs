@@ -0,0 +1,104 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use password_chef::checkpoint::Checkpoint;
+use password_chef::{generator_from_str, CandidateGenerator, GeneratorOptions};
+
+const RECIPE: &str = "mask ?d?d\nconstant+u? ab\nreplace #2 a4 b8";
+
+fn generator() -> CandidateGenerator {
+    generator_from_str(RECIPE, GeneratorOptions::default()).unwrap()
+}
+
+#[test]
+fn restore_continues_after_last_candidate() {
+    let all: Vec<String> = generator().collect();
+    for stop in [0, 1, 7, all.len() - 1, all.len()] {
+        let mut first = generator();
+        let mut output: Vec<String> = first.by_ref().take(stop).collect();
+        // Through the text format, as when saved to a file
+        let checkpoint = Checkpoint::from_text(&first.checkpoint().to_text()).unwrap();
+
+        let mut second = generator();
+        second.restore(&checkpoint).unwrap();
+        output.extend(second);
+        assert_eq!(output, all, "stopped after {}", stop);
+    }
+}
+
+#[test]
+fn limit_is_saved_and_exhausted_generator_is_finished() {
+    let mut limited = generator();
+    limited.set_limit(3);
+    assert_eq!(limited.by_ref().count(), 3);
+    let checkpoint = limited.checkpoint();
+    assert_eq!(checkpoint.limit, Some(0));
+    assert!(!checkpoint.finished);
+
+    let mut exhausted = generator();
+    exhausted.by_ref().count();
+    let checkpoint = exhausted.checkpoint();
+    assert!(checkpoint.finished);
+    let mut restored = generator();
+    restored.restore(&checkpoint).unwrap();
+    assert_eq!(restored.next(), None);
+}
+
+#[test]
+fn closed_output_keeps_last_checkpoint() {
+    let dir = tempfile::tempdir().unwrap();
+    let recipe_path = dir.path().join("recipe.txt");
+    let checkpoint_path = dir.path().join("progress.chk");
+    std::fs::write(&recipe_path, "mask ?d?d?d?d?d").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_PasswordChef"))
+        .arg("-r").arg(&recipe_path)
+        .arg("--checkpoint").arg(&checkpoint_path)
+        .args(["--checkpoint-interval", "0"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    // Read past the first checkpoint, then close the output like `| head` would
+    let num_read = BufReader::new(child.stdout.take().unwrap()).lines().take(9000).count();
+    assert_eq!(num_read, 9000);
+    let status = child.wait().unwrap();
+    assert!(!status.success());
+
+    let checkpoint = Checkpoint::read_from(checkpoint_path.to_str().unwrap()).unwrap();
+    assert!(!checkpoint.finished);
+    let mut restored = generator_from_str("mask ?d?d?d?d?d", GeneratorOptions::default()).unwrap();
+    restored.restore(&checkpoint).unwrap();
+    // Progress is kept instead of starting over
+    let next = restored.next().unwrap().parse::<usize>().unwrap();
+    assert!(next > 0 && next < 100000);
+}
+
+#[test]
+fn restore_refuses_checkpoints_for_other_recipes_or_wordlists() {
+    let mut first = generator();
+    first.by_ref().take(5).count();
+    let checkpoint = Checkpoint::from_text(&first.checkpoint().to_text()).unwrap();
+
+    // Comments and spacing don't change the recipe
+    let mut same = generator_from_str(&format!("// digits\n{}", RECIPE.replace(' ', "  ")), GeneratorOptions::default()).unwrap();
+    assert!(same.restore(&checkpoint).is_ok());
+    let mut other = generator_from_str(&RECIPE.replace("b8", "b6"), GeneratorOptions::default()).unwrap();
+    assert!(other.restore(&checkpoint).is_err());
+
+    let dir = tempfile::tempdir().unwrap();
+    let wordlist_path = dir.path().join("words.txt");
+    std::fs::write(&wordlist_path, "one\ntwo\n").unwrap();
+    let recipe = format!("wordlist {}\nmask ?d", wordlist_path.display());
+    let wordlist_gen = || generator_from_str(&recipe, GeneratorOptions::default()).unwrap();
+    let checkpoint = wordlist_gen().checkpoint();
+    assert!(wordlist_gen().restore(&checkpoint).is_ok());
+    std::fs::write(&wordlist_path, "one\ntwo\nthree\n").unwrap();
+    assert!(wordlist_gen().restore(&checkpoint).is_err());
+}
+
+#[test]
+fn checkpoints_without_fingerprints_are_rejected() {
+    let text = generator().checkpoint().to_text();
+    let old_text: String = text.lines().filter(|line| !line.starts_with("fingerprint")).map(|line| format!("{}\n", line)).collect();
+    assert!(Checkpoint::from_text(&old_text).is_err());
+}
@@ -0,0 +1,61 @@
+use std::io;
+use password_chef::{generator_from_str, GeneratorOptions, Keyspace, RecipeIncrementer};
+
+// Outputs the target's text as it is, then reversed; the reversed text is taken when incrementing,
+// like an incrementer that finds its entries in the text
+#[derive(Clone)]
+struct Reversed {
+    target: usize,
+    reversed: Option<String>
+}
+
+impl RecipeIncrementer for Reversed {
+    fn increment(&mut self, text_segments: &[String]) -> bool {
+        if self.reversed.is_some() { return false; }
+        self.reversed = Some(text_segments[self.target].chars().rev().collect());
+        true
+    }
+
+    fn reset(&mut self, _text_segments: &[String]) {
+        self.reversed = None;
+    }
+
+    fn output(&self, text_segments: &[String], outputs: &mut [String]) {
+        outputs[0].clear();
+        outputs[0].push_str(self.reversed.as_deref().unwrap_or(&text_segments[self.target]));
+    }
+
+    fn keyspace(&self) -> Keyspace {
+        Keyspace::Exact(2)
+    }
+
+    fn fork(&self) -> io::Result<Box<dyn RecipeIncrementer>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+fn options() -> GeneratorOptions {
+    let mut options = GeneratorOptions::default();
+    options.custom_steps.register_with_target("reversed", |_, _| Ok(()), |_: &(), context| {
+        Box::new(Reversed { target: context.target_seg.unwrap(), reversed: None })
+    });
+    options
+}
+
+#[test]
+fn custom_step_gets_modifiers_and_ids() {
+    let generator = generator_from_str("mask#m ab?d\nreversed+u #m\nconstant !", options()).unwrap();
+    let candidates: Vec<String> = generator.take(3).collect();
+    assert_eq!(candidates, ["AB0!", "0BA!", "AB1!"]);
+}
+
+#[test]
+fn skip_seeks_with_the_text_skipped_to() {
+    let recipe = "mask#m ?d?d\nreversed #m";
+    let all: Vec<String> = generator_from_str(recipe, options()).unwrap().collect();
+    for n in [1, 2, 37, all.len() - 1] {
+        let mut generator = generator_from_str(recipe, options()).unwrap();
+        assert!(generator.skip_candidates(n as u128));
+        assert_eq!(generator.collect::<Vec<_>>(), all[n..], "skipped {}", n);
+    }
+}
@@ -0,0 +1,32 @@
+use password_chef::{generator_from_str, CandidateGenerator, GeneratorOptions};
+use password_chef::items::wordlist::WordlistMode;
+use password_chef::parallel_generator::{self, UNITS_PER_THREAD};
+
+// Output of the generator on this many threads, as written to the output
+fn generate(candidate_gen: &CandidateGenerator, num_threads: usize, ordered: bool) -> Vec<String> {
+    let split = candidate_gen.split_work(num_threads as u128 * UNITS_PER_THREAD);
+    let mut output = Vec::new();
+    parallel_generator::generate(candidate_gen, &split, num_threads, ordered, &mut output).unwrap();
+    String::from_utf8(output).unwrap().lines().map(str::to_owned).collect()
+}
+
+#[test]
+fn threads_give_the_same_candidates() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("words.txt"), "alpha\nBeta\ngamma\ndelta\nepsilon\nzeta\n").unwrap();
+    for wordlist_mode in [WordlistMode::Memory, WordlistMode::Stream] {
+        let options = GeneratorOptions { search_dirs: vec![dir.path().to_path_buf()], wordlist_mode, ..GeneratorOptions::default() };
+        let candidate_gen = generator_from_str("wordlist+ut words.txt\nmask ?d?d", options).unwrap();
+        let single: Vec<String> = candidate_gen.fork().unwrap().collect();
+        assert_eq!(single.len(), 6 * 2 * 100);
+        assert!(candidate_gen.split_work(4 * UNITS_PER_THREAD).units() > 1);
+
+        assert_eq!(generate(&candidate_gen, 1, true), single, "{:?}", wordlist_mode);
+        assert_eq!(generate(&candidate_gen, 4, true), single, "{:?}", wordlist_mode);
+        let mut unordered = generate(&candidate_gen, 4, false);
+        let mut sorted = single.clone();
+        unordered.sort();
+        sorted.sort();
+        assert_eq!(unordered, sorted, "{:?}", wordlist_mode);
+    }
+}
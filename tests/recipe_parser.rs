@@ -0,0 +1,34 @@
+use password_chef::{generator_from_str, GeneratorOptions};
+use password_chef::recipe_parser::{RecipeParser, RecipeParseErrorKind};
+use password_chef::recipe_step::RecipeStep;
+
+fn candidates(recipe: &str) -> Vec<String> {
+    generator_from_str(recipe, GeneratorOptions::default()).unwrap().collect()
+}
+
+#[test]
+fn comments_on_their_own_line_and_after_steps() {
+    let recipe = "#comment\n  #indented\n# spaced\n// slashes\nmask#m ?d // after\nreplace #m 0o # after";
+    assert_eq!(candidates(recipe), candidates("mask#m ?d\nreplace #m 0o"));
+}
+
+#[test]
+fn non_ascii_modifiers_are_reported() {
+    for (recipe, token, column) in [("mask+u€ ?d", "€", 7), ("mask.x€ ?d", "€", 7), ("mask#m+€x.c ?d", "+€x", 7)] {
+        let errors = RecipeParser::parse(recipe.to_owned()).err().unwrap();
+        assert_eq!(errors.len(), 1, "{}", recipe);
+        assert_eq!(errors[0].kind, RecipeParseErrorKind::InvalidModifier, "{}", recipe);
+        assert_eq!((errors[0].token.as_str(), errors[0].column), (token, column), "{}", recipe);
+    }
+}
+
+#[test]
+fn charset_definition_is_the_rest_of_the_line() {
+    let recipe = RecipeParser::parse("mask ?d, charset ?1 ,.; #x //y\ncs ?2 a,b".to_owned()).unwrap();
+    let definitions: Vec<&str> = recipe.iter()
+        .filter_map(|step| if let RecipeStep::Charset { definition, .. } = step { Some(definition.as_str()) } else { None })
+        .collect();
+    assert_eq!(recipe.len(), 3);
+    assert_eq!(definitions, [",.; #x //y", "a,b"]);
+    assert_eq!(candidates("charset ?1 ,.;\nmask ?1"), [",", ".", ";"]);
+}
@@ -0,0 +1,27 @@
+use password_chef::{generator_from_str, GeneratorOptions};
+
+fn candidates(recipe: &str) -> Vec<String> {
+    generator_from_str(recipe, GeneratorOptions::default()).unwrap().collect()
+}
+
+#[test]
+fn replace_goes_through_each_match_of_each_replacement() {
+    assert_eq!(candidates("constant pass\nreplace #1 a4 s5"), ["pass", "p4ss", "pa5s", "pas5"]);
+}
+
+#[test]
+fn replace_all_goes_through_subsets_then_alternatives() {
+    // Fewer replacements first; at each site, its alternatives in the order they were written
+    let capped = ["pass", "p4ss", "p@ss", "pa5s", "pas5", "p45s", "p@5s", "p4s5", "p@s5", "pa55"];
+    assert_eq!(candidates("constant pass\nreplace+all2 #1 a4 a@ s5"), capped);
+    let mut all = capped.to_vec();
+    all.extend(["p455", "p@55"]);
+    assert_eq!(candidates("constant pass\nreplace+all #1 a4 a@ s5"), all);
+}
+
+#[test]
+fn replacements_follow_the_source_text() {
+    let recipe = "charset ?1 as\nmask#m ?1?1\n";
+    assert_eq!(candidates(&format!("{}replace #m a4", recipe)), ["aa", "4a", "a4", "as", "4s", "sa", "s4", "ss"]);
+    assert_eq!(candidates(&format!("{}replace+all #m a4", recipe)), ["aa", "4a", "a4", "44", "as", "4s", "sa", "s4", "ss"]);
+}
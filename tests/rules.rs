@@ -0,0 +1,53 @@
+use password_chef::items::rules::{parse_rule, RulesIncrementer};
+
+fn apply(rule: &str, word: &str) -> String {
+    let mut chars: Vec<char> = word.chars().collect();
+    for op in parse_rule(rule).unwrap() {
+        op.apply(&mut chars);
+    }
+    chars.into_iter().collect()
+}
+
+// Examples from hashcat's rule documentation
+#[test]
+fn functions_match_hashcat_examples() {
+    let examples = [
+        (":", "p@ssW0rd"), ("l", "p@ssw0rd"), ("u", "P@SSW0RD"), ("c", "P@ssw0rd"), ("C", "p@SSW0RD"),
+        ("t", "P@SSw0RD"), ("T3", "p@sSW0rd"), ("r", "dr0Wss@p"), ("d", "p@ssW0rdp@ssW0rd"),
+        ("p2", "p@ssW0rdp@ssW0rdp@ssW0rd"), ("f", "p@ssW0rddr0Wss@p"), ("{", "@ssW0rdp"), ("}", "dp@ssW0r"),
+        ("$1", "p@ssW0rd1"), ("^1", "1p@ssW0rd"), ("[", "@ssW0rd"), ("]", "p@ssW0r"), ("D3", "p@sW0rd"),
+        ("x04", "p@ss"), ("O12", "psW0rd"), ("i4!", "p@ss!W0rd"), ("o3$", "p@s$W0rd"), ("'6", "p@ssW0"),
+        ("ss$", "p@$$W0rd"), ("@s", "p@W0rd"), ("z2", "ppp@ssW0rd"), ("Z2", "p@ssW0rddd"),
+        ("q", "pp@@ssssWW00rrdd"), ("k", "@pssW0rd"), ("K", "p@ssW0dr"), ("*34", "p@sWs0rd"),
+        ("L2", "p@æsW0rd"), ("R2", "p@9sW0rd"), ("+2", "p@tsW0rd"), ("-1", "p?ssW0rd"), (".1", "psssW0rd"),
+        (",1", "ppssW0rd"), ("y2", "p@p@ssW0rd"), ("Y2", "p@ssW0rdrd")
+    ];
+    for (rule, expected) in examples {
+        assert_eq!(apply(rule, "p@ssW0rd"), expected, "rule {}", rule);
+    }
+    assert_eq!(apply("E", "p@ssW0rd w0rld"), "P@ssw0rd W0rld");
+    assert_eq!(apply("e-", "p@ssW0rd-w0rld"), "P@ssw0rd-W0rld");
+    assert_eq!(apply("30-", "pass-word-word"), "pass-Word-word");
+    assert_eq!(apply("31-", "pass-word-word"), "pass-word-Word");
+}
+
+#[test]
+fn functions_combine_and_ignore_spaces() {
+    assert_eq!(apply("c $1 $2", "password"), "Password12");
+    // Positions past the end leave the word unchanged
+    assert_eq!(apply("D9 x38 y9", "abc"), "abc");
+}
+
+#[test]
+fn unsupported_rules_are_skipped_with_warnings() {
+    for rule in ["<5", "!a", "Q", "M", "4", "h", "$"] {
+        assert!(parse_rule(rule).is_err(), "rule {}", rule);
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.rule");
+    std::fs::write(&path, "# comment\nu\n>5\n\n$1 $2\nX021\n").unwrap();
+    let (_, warnings) = RulesIncrementer::new(0, path.to_string_lossy().into_owned()).unwrap();
+    assert_eq!(warnings.len(), 2);
+    assert!(warnings[0].contains("line 3") && warnings[0].contains("rejection rule `>`"), "{}", warnings[0]);
+    assert!(warnings[1].contains("line 6") && warnings[1].contains("memory rule `X`"), "{}", warnings[1]);
+}